@@ -1,49 +1,589 @@
 use std::collections::BTreeMap;
 
 use crate::{
-    arg::ResourceOptions, daemonset, deployment, job, nodes_by, pods_by, resources,
-    spreading_status, statefulset, CachedNodeApi, TopologyTable, TopologyTables,
+    arg::{NamespaceScopeOptions, ResourceOptions},
+    daemonset, deployment, is_forbidden, job, min_domains_finding, namespace_labels,
+    node::UNKNOWN,
+    nodes_by_workloads, pods_by_workloads, resources, skew_table, statefulset,
+    view::Progress,
+    CachedNodeApi, TopologyTables, Workload,
 };
 use anyhow::*;
-use k8s_openapi::api::{
-    apps::v1::{DaemonSet, Deployment, StatefulSet},
-    batch::v1::Job,
+use futures::{stream, StreamExt, TryStreamExt};
+use k8s_openapi::{
+    api::{
+        apps::v1::{DaemonSet, Deployment, StatefulSet},
+        batch::v1::Job,
+        core::v1::{Node, Pod},
+    },
+    NamespaceResourceScope,
 };
-use kube::Client;
+use kube::{api::TypeMeta, Client, Resource, ResourceExt};
+use serde::de::DeserializeOwned;
+use std::{fmt::Debug, time::Instant};
 
-pub async fn all(opts: ResourceOptions, cli: Client) -> Result<TopologyTables> {
-    let namespace = opts.namespace().unwrap_or(cli.default_namespace());
+// Header segment of combined tables, in place of the kind of workloads
+pub const AGGREGATE_HEADER: &str = "aggregate";
+
+pub async fn all(
+    opts: ResourceOptions,
+    scope: NamespaceScopeOptions,
+    cli: Client,
+    node_api: &CachedNodeApi,
+) -> Result<TopologyTables> {
+    let topology_keys = [opts.topology_key.clone()];
+    let mut tables = all_by_keys(opts, scope, &topology_keys, cli, node_api).await?;
+    Ok(tables.remove(0))
+}
+
+// Workloads, pods and nodes are listed once and measured against each key,
+// tables are returned in the order of the keys
+pub async fn all_by_keys(
+    opts: ResourceOptions,
+    scope: NamespaceScopeOptions,
+    topology_keys: &[String],
+    cli: Client,
+    node_api: &CachedNodeApi,
+) -> Result<Vec<TopologyTables>> {
+    let mut namespace_labels = if scope.all_namespaces {
+        namespace_labels(cli.clone())
+            .await?
+            .into_iter()
+            .filter(|(namespace, _)| !scope.is_excluded(namespace))
+            .collect::<BTreeMap<_, _>>()
+    } else {
+        let namespace = opts.namespace().unwrap_or(cli.default_namespace());
+        BTreeMap::from([(namespace.to_string(), BTreeMap::new())])
+    };
+    let namespaces = namespace_labels.keys().cloned().collect::<Vec<_>>();
+
+    let progress = Progress::new(namespaces.len());
+
+    // Namespaces are scanned concurrently, but bounded to avoid flooding the API server
+    let scan = |namespace: String| {
+        let cli = cli.clone();
+        let opts = &opts;
+        let aggregate_by = scope.aggregate_by_label.as_deref();
+        let progress = &progress;
+        async move {
+            let tables =
+                all_in(opts, &namespace, topology_keys, aggregate_by, node_api, cli).await?;
+            progress.inc(&namespace);
+            Ok((namespace, tables))
+        }
+    };
+    let results = stream::iter(namespaces)
+        .map(scan)
+        .buffer_unordered(scope.concurrency.max(1))
+        .try_collect::<Vec<_>>()
+        .await;
+    progress.finish();
+    let results = results?;
+
+    let mut tables = topology_keys
+        .iter()
+        .map(|_| TopologyTables::default())
+        .collect::<Vec<_>>();
+
+    for (namespace, tables_by_key) in results {
+        // Prefixed with the namespace to distinguish the same name,
+        // and with the label value before it so that tables of a team are listed together
+        let labels = namespace_labels.remove(&namespace).unwrap_or_default();
+        for (tables, namespace_tables) in tables.iter_mut().zip(tables_by_key) {
+            let namespace_tables = match &scope.aggregate_by_namespace_label {
+                Some(key) => {
+                    let group = labels.get(key).map(String::as_str).unwrap_or(UNKNOWN);
+                    let prefix = format!("{group}/{namespace}");
+                    namespace_tables.prefixed(&prefix).with_group(group)
+                }
+                None if scope.all_namespaces => namespace_tables.prefixed(&namespace),
+                None => namespace_tables,
+            };
+            tables.append(namespace_tables);
+        }
+    }
+
+    Ok(tables)
+}
+
+async fn all_in(
+    opts: &ResourceOptions,
+    namespace: &str,
+    topology_keys: &[String],
+    aggregate_by: Option<&str>,
+    node_api: &CachedNodeApi,
+    cli: Client,
+) -> Result<Vec<TopologyTables>> {
     let selectors = opts.selectors();
-    let topology_key = &opts.topology_key;
 
-    let mut labels_set: BTreeMap<String, String> = BTreeMap::new();
+    let mut labels_set: BTreeMap<String, Workload> = BTreeMap::new();
+    let mut skipped = Vec::new();
+    let mut errors = Vec::new();
 
+    let start = Instant::now();
     let deployments =
-        resources::<Deployment>(None, namespace, Some(&selectors), cli.clone()).await?;
-    labels_set.extend(deployment::labels_set_by(&deployments)?);
+        resources_or_skip::<Deployment>(namespace, &selectors, cli.clone(), &mut skipped).await?;
+    let workloads = collect_workloads(&deployments, deployment::labels_set_by, &mut errors);
+    labels_set.extend(workloads);
+    if opts.exclude_surge {
+        deployment::limit_surge(&mut labels_set, &deployments);
+    }
 
     let statefulsets =
-        resources::<StatefulSet>(None, namespace, Some(&selectors), cli.clone()).await?;
-    labels_set.extend(statefulset::labels_set_by(&statefulsets)?);
+        resources_or_skip::<StatefulSet>(namespace, &selectors, cli.clone(), &mut skipped).await?;
+    let workloads = collect_workloads(&statefulsets, statefulset::labels_set_by, &mut errors);
+    labels_set.extend(workloads);
+
+    let jobs = resources_or_skip::<Job>(namespace, &selectors, cli.clone(), &mut skipped).await?;
+    labels_set.extend(collect_workloads(&jobs, job::labels_set_by, &mut errors));
+
+    let daemonsets =
+        resources_or_skip::<DaemonSet>(namespace, &selectors, cli.clone(), &mut skipped).await?;
+    let workloads = collect_workloads(&daemonsets, daemonset::labels_set_by, &mut errors);
+    labels_set.extend(workloads);
+
+    let profiler = node_api.profiler();
+    profiler.record("list workloads", namespace, start.elapsed());
+
+    // Workloads exempted by annotation are left out of the cluster-wide scan
+    labels_set.retain(|name, workload| {
+        if workload.exempt {
+            log::info!("Skip exempt workload: {name}");
+        }
+        !workload.exempt
+    });
 
-    let jobs = resources::<Job>(None, namespace, Some(&selectors), cli.clone()).await?;
-    labels_set.extend(job::labels_set_by(&jobs)?);
+    let pods_map = pods_by_workloads(&labels_set, namespace, opts.chunk_size, cli);
+    let pods_map = profiler.timed("list pods", namespace, pods_map).await?;
+    let workloads = nodes_by_workloads(labels_set, pods_map, node_api).await?;
 
-    let daemonsets = resources::<DaemonSet>(None, namespace, Some(&selectors), cli.clone()).await?;
-    labels_set.extend(daemonset::labels_set_by(&daemonsets)?);
+    let mut tables_by_key = topology_keys
+        .iter()
+        .map(|topology_key| {
+            tables_of(&workloads, topology_key, aggregate_by, node_api)
+                .with_scope(topology_key, Some(namespace))
+        })
+        .collect::<Vec<_>>();
 
+    // Skipped and malformed resources are the same for every key, so reported once
+    if let Some(tables) = tables_by_key.first_mut() {
+        tables.skipped = skipped;
+        tables.errors = errors;
+    }
+
+    Ok(tables_by_key)
+}
+
+fn tables_of(
+    workloads: &[(String, Workload, Vec<Pod>, Vec<Node>)],
+    topology_key: &str,
+    aggregate_by: Option<&str>,
+    node_api: &CachedNodeApi,
+) -> TopologyTables {
     let mut tables = TopologyTables::default();
 
-    let node_api = CachedNodeApi::try_from(cli.clone()).await?;
+    // Pods of workloads sharing the label value are summed into one more table
+    let mut aggregates: BTreeMap<String, (Vec<Pod>, Vec<Node>)> = BTreeMap::new();
+
+    for (name, workload, pods, nodes) in workloads {
+        let start = Instant::now();
+        let domains = node_api.domains_of_nodes(topology_key, nodes);
+        let finding = min_domains_finding(name, workload, topology_key, &domains, node_api);
+        tables.findings.extend(finding);
 
-    for (name, labels) in labels_set {
-        let pods = pods_by(&[&labels], namespace, cli.clone()).await?;
-        let nodes = nodes_by(&pods, &node_api).await?;
-        let (topology_values, domains) = spreading_status(&nodes, topology_key, &node_api).await?;
-        let table = TopologyTable::create(topology_values, &domains, Some(name));
+        if let Some(value) = aggregate_by.and_then(|key| workload.labels.get(key)) {
+            let (all_pods, all_nodes) = aggregates.entry(value.clone()).or_default();
+            all_pods.extend(pods.iter().cloned());
+            all_nodes.extend(nodes.iter().cloned());
+        }
+
+        let table = skew_table(pods, nodes, &domains, topology_key, Some(name.clone()))
+            .with_allowed(workload.max_skew(topology_key))
+            .with_soft(workload.is_soft(topology_key))
+            .with_constraint(workload.constraint_keys());
+        node_api.profiler().record("compute", name, start.elapsed());
 
         tables.insert(table);
     }
 
-    Ok(tables)
+    for (value, (pods, nodes)) in aggregates {
+        let domains = node_api.domains_of_nodes(topology_key, &nodes);
+        let header = format!("{AGGREGATE_HEADER}/{value}");
+        tables.insert(skew_table(
+            &pods,
+            &nodes,
+            &domains,
+            topology_key,
+            Some(header),
+        ));
+    }
+
+    tables
+}
+
+// A malformed workload is recorded as an error instead of aborting the whole scan
+fn collect_workloads<K>(
+    objects: &[K],
+    labels_set_by: fn(&[K]) -> Result<BTreeMap<String, Workload>>,
+    errors: &mut Vec<String>,
+) -> BTreeMap<String, Workload>
+where
+    K: Resource<DynamicType = ()> + Clone,
+{
+    let mut labels_set = BTreeMap::new();
+
+    for object in objects {
+        match labels_set_by(std::slice::from_ref(object)) {
+            Result::Ok(workloads) => labels_set.extend(workloads),
+            Err(e) => {
+                let meta = TypeMeta::resource::<K>();
+                let kind = meta.kind.to_lowercase();
+                let name = object.name_any();
+                errors.push(format!("{}/{kind}/{name}: {e}", meta.api_version));
+            }
+        }
+    }
+
+    labels_set
+}
+
+// Kinds the user is not allowed to list are skipped instead of failing the whole scan
+async fn resources_or_skip<K>(
+    namespace: &str,
+    selectors: &str,
+    cli: Client,
+    skipped: &mut Vec<String>,
+) -> Result<Vec<K>>
+where
+    K: Resource<Scope = NamespaceResourceScope, DynamicType = ()>,
+    K: Clone + DeserializeOwned + Debug,
+{
+    match resources::<K>(None, namespace, Some(selectors), cli).await {
+        Err(e) if is_forbidden(&e) => {
+            let meta = TypeMeta::resource::<K>();
+            let kind = format!("{}/{}", meta.api_version, meta.kind.to_lowercase());
+            log::warn!("Skip forbidden resources: {kind} in {namespace}");
+            skipped.push(kind);
+            Ok(Vec::new())
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::{Namespace, Node, Pod};
+    use kube::{
+        api::{ListMeta, ObjectList, TypeMeta},
+        Client,
+    };
+    use serde::Deserialize;
+
+    use crate::kube::tests::{create_objects, load_objects, send_status};
+
+    use super::*;
+    use futures::pin_mut;
+    use http::{Request, Response};
+    use kube::client::Body;
+    use std::time::Duration;
+    use tower_test::mock;
+
+    #[tokio::test]
+    async fn all_no_options() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/nodes.yaml", Node);
+            create_objects!(handle, "../tests/deploy_max_skew_deploy.yaml", Deployment);
+            create_objects!(handle, "../tests/empty.yaml", StatefulSet);
+            create_objects!(handle, "../tests/empty.yaml", Job);
+            create_objects!(handle, "../tests/empty.yaml", DaemonSet);
+            create_objects!(handle, "../tests/deploy_no_options_pods.yaml", Pod);
+
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceOptions {
+            namespace: Some(ns.to_owned()),
+            ..Default::default()
+        };
+        let scope = NamespaceScopeOptions::default();
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = all(opts, scope, cli, &node_api).await?;
+
+        let mut topology_table_iter = topology_tables.into_iter();
+
+        let topology_table1 = topology_table_iter.next().unwrap();
+        assert_eq!(
+            topology_table1.header,
+            Some(String::from("apps/v1/deployment/deploy1"))
+        );
+        assert_eq!(
+            topology_table1.constraint.as_deref(),
+            Some("topology.kubernetes.io/zone")
+        );
+        for topology in topology_table1.topologies {
+            assert_eq!(topology.count, 1);
+            assert_eq!(topology.allowed, Some(1));
+        }
+
+        let topology_table2 = topology_table_iter.next().unwrap();
+        assert_eq!(
+            topology_table2.header,
+            Some(String::from("apps/v1/deployment/deploy2"))
+        );
+        assert_eq!(
+            topology_table2.constraint.as_deref(),
+            Some("kubernetes.io/hostname")
+        );
+        for topology in topology_table2.topologies {
+            assert_eq!(topology.count, 1);
+            assert_eq!(topology.allowed, None);
+        }
+
+        assert!(topology_table_iter.next().is_none());
+
+        spawned.await??;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn all_aggregate_by_namespace_label() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/nodes.yaml", Node);
+            create_objects!(handle, "../tests/namespaces_team.yaml", Namespace);
+            create_objects!(handle, "../tests/deploy_max_skew_deploy.yaml", Deployment);
+            create_objects!(handle, "../tests/empty.yaml", StatefulSet);
+            create_objects!(handle, "../tests/empty.yaml", Job);
+            create_objects!(handle, "../tests/empty.yaml", DaemonSet);
+            create_objects!(handle, "../tests/deploy_no_options_pods.yaml", Pod);
+
+            Ok(())
+        });
+
+        let cli = Client::new(mock_service, "default");
+        let opts = ResourceOptions::default();
+        let scope = NamespaceScopeOptions {
+            all_namespaces: true,
+            aggregate_by_namespace_label: Some(String::from("team")),
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = all(opts, scope, cli, &node_api).await?;
+        spawned.await??;
+
+        let headers = topology_tables
+            .iter()
+            .filter_map(|table| table.header.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            headers,
+            [
+                "payments/default/apps/v1/deployment/deploy1",
+                "payments/default/apps/v1/deployment/deploy2"
+            ]
+        );
+        assert!(topology_tables
+            .iter()
+            .all(|table| table.group.as_deref() == Some("payments")));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn all_aggregate_by_label() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/nodes.yaml", Node);
+            create_objects!(handle, "../tests/deploy_part_of_deploy.yaml", Deployment);
+            create_objects!(handle, "../tests/empty.yaml", StatefulSet);
+            create_objects!(handle, "../tests/empty.yaml", Job);
+            create_objects!(handle, "../tests/empty.yaml", DaemonSet);
+            create_objects!(handle, "../tests/deploy_no_options_pods.yaml", Pod);
+
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceOptions {
+            namespace: Some(ns.to_owned()),
+            ..Default::default()
+        };
+        let scope = NamespaceScopeOptions {
+            aggregate_by_label: Some(String::from("app.kubernetes.io/part-of")),
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = all(opts, scope, cli, &node_api).await?;
+        spawned.await??;
+
+        assert_eq!(topology_tables.len(), 3);
+        let aggregate = topology_tables
+            .iter()
+            .find(|table| table.header.as_deref() == Some("aggregate/shop"))
+            .context("No found aggregate table")?;
+        assert_eq!(aggregate.total(), 6);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn all_skip_forbidden() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/nodes.yaml", Node);
+            create_objects!(handle, "../tests/deploy_max_skew_deploy.yaml", Deployment);
+            create_objects!(handle, "../tests/empty.yaml", StatefulSet);
+
+            send_status!(handle, 403);
+
+            create_objects!(handle, "../tests/empty.yaml", DaemonSet);
+            create_objects!(handle, "../tests/deploy_no_options_pods.yaml", Pod);
+
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceOptions {
+            namespace: Some(ns.to_owned()),
+            ..Default::default()
+        };
+        let scope = NamespaceScopeOptions::default();
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = all(opts, scope, cli, &node_api).await?;
+        spawned.await??;
+
+        assert_eq!(topology_tables.skipped, vec!["batch/v1/job"]);
+        assert_eq!(topology_tables.len(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn all_workloads_concurrently() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            send_status!(handle, 403);
+            create_objects!(handle, "../tests/deploy_max_skew_deploy.yaml", Deployment);
+            create_objects!(handle, "../tests/empty.yaml", StatefulSet);
+            create_objects!(handle, "../tests/empty.yaml", Job);
+            create_objects!(handle, "../tests/empty.yaml", DaemonSet);
+            create_objects!(handle, "../tests/deploy_disjoint_pods.yaml", Pod);
+
+            // Nodes of both workloads are requested before any of them is answered
+            let nodes = load_objects!("../tests/nodes.yaml", Node);
+            let respond = |name: &str, send: mock::SendResponse<Response<Body>>| {
+                let node = nodes.iter().find(|node| node.name_any() == name);
+                let node = node.context("No found node")?;
+                send.send_response(
+                    Response::builder().body(Body::from(serde_json::to_vec(node)?))?,
+                );
+                anyhow::Ok(())
+            };
+            let mut pending = Vec::new();
+            while !["node1", "node2"]
+                .iter()
+                .all(|name| pending.iter().any(|(n, _)| n == name))
+            {
+                let next = tokio::time::timeout(Duration::from_secs(5), handle.next_request());
+                let (request, send) = next.await?.unwrap();
+                let name = request.uri().path().rsplit('/').next().unwrap_or_default();
+                pending.push((name.to_string(), send));
+            }
+            for (name, send) in pending {
+                respond(&name, send)?;
+            }
+            while let Some((request, send)) = handle.next_request().await {
+                respond(
+                    request.uri().path().rsplit('/').next().unwrap_or_default(),
+                    send,
+                )?;
+            }
+
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceOptions {
+            namespace: Some(ns.to_owned()),
+            ..Default::default()
+        };
+        let scope = NamespaceScopeOptions::default();
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = all(opts, scope, cli, &node_api).await?;
+        drop(node_api);
+        spawned.await??;
+
+        // Without the node list, each workload counts only the domains of its own nodes
+        let domains = topology_tables
+            .iter()
+            .map(|table| {
+                (&table.topologies)
+                    .into_iter()
+                    .map(|topology| topology.key.as_str())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            domains,
+            vec![
+                vec!["asia-northeast1-a"],
+                vec!["asia-northeast1-b", "asia-northeast1-c"],
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn all_continue_on_error() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/nodes.yaml", Node);
+            create_objects!(handle, "../tests/deploy_malformed_deploy.yaml", Deployment);
+            create_objects!(handle, "../tests/empty.yaml", StatefulSet);
+            create_objects!(handle, "../tests/empty.yaml", Job);
+            create_objects!(handle, "../tests/empty.yaml", DaemonSet);
+            create_objects!(handle, "../tests/deploy_no_options_pods.yaml", Pod);
+
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceOptions {
+            namespace: Some(ns.to_owned()),
+            ..Default::default()
+        };
+        let scope = NamespaceScopeOptions::default();
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = all(opts, scope, cli, &node_api).await?;
+        spawned.await??;
+
+        assert_eq!(
+            topology_tables.errors,
+            vec!["apps/v1/deployment/deploy3: No found selector"]
+        );
+        assert_eq!(topology_tables.len(), 2);
+
+        Ok(())
+    }
 }
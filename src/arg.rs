@@ -1,17 +1,30 @@
-use crate::kube::{Label, LabelSelector};
+use crate::{
+    kube::{Label, LabelSelector, Operator},
+    DomainFilter, Thresholds,
+};
 use anyhow::*;
 use clap::builder::{
     styling::{AnsiColor, Effects},
     Styles,
 };
 use clap::{Parser, Subcommand, ValueEnum};
+use regex::Regex;
 use std::{
-    collections::BTreeMap,
+    ffi::OsString,
     fmt::{Display, Formatter},
+    path::PathBuf,
+    time::Duration,
 };
 use strum::AsRefStr;
 
-const DEFAULT_ZONE_LABEL: &str = "topology.kubernetes.io/zone";
+pub const DEFAULT_ZONE_LABEL: &str = "topology.kubernetes.io/zone";
+pub const DEFAULT_CHUNK_SIZE: u32 = 500;
+const DEFAULT_CONCURRENCY: usize = 8;
+const DEFAULT_REPORT_NAME: &str = "topology-skew";
+const DEFAULT_HISTORY_LAST: usize = 10;
+const DEFAULT_MANIFEST_NAMESPACE: &str = "default";
+const DEFAULT_MANIFEST_SCHEDULE: &str = "*/30 * * * *";
+const SYSTEM_NAMESPACES: [&str; 3] = ["kube-system", "kube-public", "kube-node-lease"];
 
 fn help_styles() -> Styles {
     Styles::styled()
@@ -21,11 +34,41 @@ fn help_styles() -> Styles {
         .placeholder(AnsiColor::Cyan.on_default())
 }
 
+// Namespace and domain patterns must match the whole name
+pub(crate) fn parse_full_regex(s: &str) -> Result<Regex> {
+    Ok(Regex::new(&format!("^(?:{s})$"))?)
+}
+
+// Same as kubectl, a bare `0` is accepted without a unit
+fn parse_duration(s: &str) -> Result<Duration> {
+    if s == "0" {
+        return Ok(Duration::ZERO);
+    }
+    Ok(humantime::parse_duration(s)?)
+}
+
+// Each of comma-separated requirements, like kubectl -l app=web,tier!=db,!debug
 fn parse_key_val(s: &str) -> Result<Label> {
-    let pos = s
-        .find('=')
-        .context("Not found `=` in key value pair(KEY=VALUE)")?;
-    Ok(Label(s[..pos].parse()?, s[pos + 1..].parse()?))
+    if let Some((key, value)) = s.split_once("!=") {
+        return Ok(Label(
+            key.to_string(),
+            value.to_string(),
+            Operator::NotEqual,
+        ));
+    }
+    if let Some((key, value)) = s.split_once("==").or_else(|| s.split_once('=')) {
+        return Ok(Label(key.to_string(), value.to_string(), Operator::Equal));
+    }
+
+    // Existence of the key, e.g. app or !app
+    let (key, operator) = match s.strip_prefix('!') {
+        Some(key) => (key, Operator::NotExists),
+        None => (s, Operator::Exists),
+    };
+    if key.is_empty() {
+        bail!("Not found a key in the requirement(KEY=VALUE, KEY!=VALUE, KEY or !KEY)");
+    }
+    Ok(Label(key.to_string(), String::new(), operator))
 }
 
 #[derive(Parser, Debug)]
@@ -35,35 +78,305 @@ pub struct Args {
     pub kube_options: KubeConfigOptions,
 
     /// Output format
-    #[arg(short, long, global = true, default_value_t = OutputFormat::Text)]
+    #[arg(short, long, global = true, default_value_t = OutputFormat::Text, env = "KTS_OUTPUT")]
     pub output: OutputFormat,
 
+    /// Write output to the file instead of stdout
+    #[arg(long, global = true, env = "KTS_OUTPUT_FILE")]
+    pub output_file: Option<PathBuf>,
+
+    /// Append to the output file instead of truncating it
+    #[arg(long, global = true, requires = "output_file", env = "KTS_APPEND")]
+    pub append: bool,
+
+    /// Style of text tables, ascii for terminals and log systems without unicode
+    #[arg(long, global = true, default_value_t = TableStyle::Blank, env = "KTS_STYLE")]
+    pub style: TableStyle,
+
+    /// Maximum width of text tables, defaults to $COLUMNS of the terminal. 0 means unlimited
+    #[arg(long, global = true, env = "KTS_MAX_WIDTH")]
+    pub max_width: Option<usize>,
+
+    /// Nest json/yaml tables under keys such as deployment and statefulset
+    #[arg(long, global = true, env = "KTS_GROUP_OUTPUT")]
+    pub group_output: Option<GroupOutput>,
+
+    /// Version of the json/yaml output, v2 wraps tables in an object with skipped resources and errors
+    #[arg(long, global = true, default_value_t = OutputVersion::V1, env = "KTS_OUTPUT_VERSION")]
+    pub output_version: OutputVersion,
+
+    /// Order of tables when multiple tables are printed
+    #[arg(long, global = true, default_value_t = TableOrder::Name, env = "KTS_ORDER_TABLES_BY")]
+    pub order_tables_by: TableOrder,
+
+    /// Print only tables that have any skew
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "min_skew",
+        env = "KTS_ONLY_SKEWED"
+    )]
+    pub only_skewed: bool,
+
+    /// Print only tables whose max skew is greater than or equal to this value
+    #[arg(long, global = true, env = "KTS_MIN_SKEW")]
+    pub min_skew: Option<u32>,
+
+    /// Add a STATUS column of OK/WARN/CRIT, CRIT when the skew exceeds the declared maxSkew
+    #[arg(long, global = true, env = "KTS_STATUS")]
+    pub status: bool,
+
+    /// Skew to mark WARN in the STATUS column
+    #[arg(long, global = true, env = "KTS_WARN_SKEW")]
+    pub warn_skew: Option<u32>,
+
+    /// Skew to mark CRIT in the STATUS column instead of the declared maxSkew
+    #[arg(long, global = true, env = "KTS_CRIT_SKEW")]
+    pub crit_skew: Option<u32>,
+
+    /// Add IDEAL and DELTA columns of an even spread over the domains and the difference from it
+    #[arg(long, global = true, env = "KTS_IDEAL_COLUMNS")]
+    pub ideal_columns: bool,
+
+    /// Print only tables that count at least this many pods, counted after --include-domain and --exclude-domain
+    #[arg(long, global = true, env = "KTS_MIN_REPLICAS")]
+    pub min_replicas: Option<u32>,
+
+    /// Domain name or regex to analyze, e.g. asia-northeast1-.*. Skew is calculated among the remaining domains
+    #[arg(long, global = true, value_parser = parse_full_regex, env = "KTS_INCLUDE_DOMAIN")]
+    pub include_domain: Vec<Regex>,
+
+    /// Domain name or regex to exclude from the analysis
+    #[arg(long, global = true, value_parser = parse_full_regex, env = "KTS_EXCLUDE_DOMAIN")]
+    pub exclude_domain: Vec<Regex>,
+
+    /// Write results as a TopologySkewReport custom resource in the current namespace
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        num_args = 0..=1,
+        default_missing_value = DEFAULT_REPORT_NAME,
+        conflicts_with = "contexts",
+        env = "KTS_WRITE_REPORT_CR"
+    )]
+    pub write_report_cr: Option<String>,
+
+    /// Keep running and scan at this interval, e.g. 5m
+    #[arg(long, global = true, value_parser = parse_duration, requires = "append_to", env = "KTS_EVERY")]
+    pub every: Option<Duration>,
+
+    /// Append a JSON record per interval to the file with --every
+    #[arg(long, global = true, requires = "every", env = "KTS_APPEND_TO")]
+    pub append_to: Option<PathBuf>,
+
+    /// Exit with 0 once the max skew of all tables is at most this value with --every
+    #[arg(long, global = true, requires = "every", env = "KTS_UNTIL_SKEW")]
+    pub until_skew: Option<u32>,
+
+    /// Exit with 3 once a table exceeds its declared maxSkew with --every
+    #[arg(long, global = true, requires = "every", env = "KTS_UNTIL_VIOLATION")]
+    pub until_violation: bool,
+
+    /// Print the API calls to be made, to check RBAC and the scope before a heavy scan
+    #[arg(long, global = true, env = "KTS_DRY_RUN")]
+    pub dry_run: Option<DryRun>,
+
+    /// Exit 0 with an empty table when no objects match, and with no output when a named resource is missing
+    #[arg(
+        long,
+        global = true,
+        visible_alias = "allow-empty",
+        env = "KTS_IGNORE_NOT_FOUND"
+    )]
+    pub ignore_not_found: bool,
+
+    /// Print how long listing nodes and pods and the computation took to stderr
+    #[arg(long, global = true, env = "KTS_PROFILE")]
+    pub profile: bool,
+
+    /// Do not pipe output into a pager
+    #[arg(long, global = true, env = "KTS_NO_PAGER")]
+    pub no_pager: bool,
+
+    /// Verbosity of logs to stderr, repeat for more (-v warn, -vv info, -vvv debug, -vvvv trace)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, env = "KTS_VERBOSE")]
+    pub verbose: u8,
+
+    /// Format of logs to stderr
+    #[arg(long, global = true, default_value_t = LogFormat::Text, env = "KTS_LOG_FORMAT")]
+    pub log_format: LogFormat,
+
+    /// Defaults to all in the current namespace
     #[command(subcommand)]
-    pub(crate) sub: SubCommand,
+    pub sub: Option<SubCommand>,
+}
+
+impl Args {
+    pub fn min_skew(&self) -> Option<u32> {
+        self.min_skew.or(self.only_skewed.then_some(1))
+    }
+
+    // STATUS column is shown with --status or any threshold
+    pub fn thresholds(&self) -> Option<Thresholds> {
+        let enabled = self.status || self.warn_skew.is_some() || self.crit_skew.is_some();
+        enabled.then_some(Thresholds {
+            warn: self.warn_skew,
+            crit: self.crit_skew,
+        })
+    }
+
+    // None analyzes all domains
+    pub fn domain_filter(&self) -> Option<DomainFilter> {
+        let enabled = !self.include_domain.is_empty() || !self.exclude_domain.is_empty();
+        enabled.then(|| DomainFilter {
+            include: self.include_domain.clone(),
+            exclude: self.exclude_domain.clone(),
+        })
+    }
+
+    pub fn log_level(&self) -> log::LevelFilter {
+        match self.verbose {
+            0 => log::LevelFilter::Error,
+            1 => log::LevelFilter::Warn,
+            2 => log::LevelFilter::Info,
+            3 => log::LevelFilter::Debug,
+            _ => log::LevelFilter::Trace,
+        }
+    }
 }
 
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
 pub struct KubeConfigOptions {
+    /// Path to the kubeconfig file, multiple paths are merged like $KUBECONFIG
+    #[arg(long, global = true, env = "KTS_KUBECONFIG")]
+    pub kubeconfig: Option<OsString>,
+
     /// Kubernetes config context
-    #[arg(long, global = true)]
+    #[arg(long, global = true, env = "KTS_CONTEXT")]
     pub context: Option<String>,
 
+    /// Kubernetes config contexts to aggregate, comma separated, glob patterns are allowed
+    #[arg(
+        long,
+        global = true,
+        value_delimiter = ',',
+        conflicts_with = "context",
+        env = "KTS_CONTEXTS"
+    )]
+    pub contexts: Vec<String>,
+
     /// Kubernetes config cluster
-    #[arg(long, global = true)]
+    #[arg(long, global = true, env = "KTS_CLUSTER")]
     pub cluster: Option<String>,
 
     /// Kubernetes config user
-    #[arg(long, global = true)]
+    #[arg(long, global = true, env = "KTS_USER")]
     pub user: Option<String>,
+
+    /// Address of the Kubernetes API server
+    #[arg(long, global = true, env = "KTS_SERVER")]
+    pub server: Option<String>,
+
+    /// Bearer token for authentication to the API server
+    #[arg(long, global = true, env = "KTS_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+
+    /// Path to a cert file for the certificate authority
+    #[arg(long, global = true, env = "KTS_CERTIFICATE_AUTHORITY")]
+    pub certificate_authority: Option<String>,
+
+    /// Skip verification of the server's certificate
+    #[arg(long, global = true, env = "KTS_INSECURE_SKIP_TLS_VERIFY")]
+    pub insecure_skip_tls_verify: bool,
+
+    /// URL of the proxy to the API server, $HTTPS_PROXY and $NO_PROXY are used if not given
+    #[arg(long, global = true, env = "KTS_PROXY_URL")]
+    pub proxy_url: Option<String>,
+
+    /// Maximum queries per second to the API server, unlimited if not given
+    #[arg(long, global = true, env = "KTS_QPS")]
+    pub qps: Option<f64>,
+
+    /// Maximum burst of queries to the API server with --qps
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 10,
+        requires = "qps",
+        env = "KTS_BURST"
+    )]
+    pub burst: u64,
+
+    /// Timeout of a single server request, e.g. 30s, 1m. 0 means no timeout, the default of kube when not given
+    #[arg(long, global = true, value_parser = parse_duration, env = "KTS_REQUEST_TIMEOUT")]
+    pub request_timeout: Option<Duration>,
+
+    /// Path to a JSON cache of nodes, used instead of listing nodes until it expires
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "contexts",
+        env = "KTS_NODE_CACHE"
+    )]
+    pub node_cache: Option<PathBuf>,
+
+    /// Time to live of the node cache, e.g. 10m
+    #[arg(long, global = true, default_value = "10m", value_parser = parse_duration, requires = "node_cache", env = "KTS_CACHE_TTL")]
+    pub cache_ttl: Duration,
+}
+
+impl KubeConfigOptions {
+    pub fn has_connection_flags(&self) -> bool {
+        self.server.is_some()
+            || self.token.is_some()
+            || self.certificate_authority.is_some()
+            || self.insecure_skip_tls_verify
+    }
 }
 
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Clone)]
 pub enum SubCommand {
+    #[command(flatten)]
+    Scan(ScanCommand),
+    /// Record and show skew history in a local snapshot store
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+    /// Print JSON Schema of the json/yaml output
+    Schema,
+    /// Manage the node cache of --node-cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Print manifests to run the tool in-cluster
+    Generate {
+        #[command(subcommand)]
+        target: GenerateTarget,
+    },
+    /// Print topology skew of two contexts side by side
+    Compare {
+        #[command(flatten)]
+        options: CompareOptions,
+
+        #[command(subcommand)]
+        target: ScanCommand,
+    },
+}
+
+// Subcommands that scan the cluster, which compare runs against each context
+#[derive(Subcommand, Debug, Clone)]
+pub enum ScanCommand {
     /// Print pod topology skew
     #[command(visible_alias("po"))]
     Pod {
         #[command(flatten)]
         options: ResourceOptions,
+
+        #[command(flatten)]
+        filter: PodFilterOptions,
     },
     /// Print deployment topology skew
     #[command(visible_alias("deploy"))]
@@ -92,6 +405,9 @@ pub enum SubCommand {
     All {
         #[command(flatten)]
         options: ResourceOptions,
+
+        #[command(flatten)]
+        scope: NamespaceScopeOptions,
     },
     /// Print node topology skew
     #[command(visible_alias("no"))]
@@ -99,21 +415,165 @@ pub enum SubCommand {
         #[command(flatten)]
         options: NodeOptions,
     },
+    /// Print topology skew of all resources per region and per zone
+    Summary {
+        #[command(flatten)]
+        options: SummaryOptions,
+
+        #[command(flatten)]
+        scope: NamespaceScopeOptions,
+    },
+}
+
+impl ScanCommand {
+    pub fn chunk_size(&self) -> u32 {
+        match self {
+            ScanCommand::Pod { options, .. } | ScanCommand::All { options, .. } => {
+                options.chunk_size
+            }
+            ScanCommand::Summary { options, .. } => options.chunk_size,
+            ScanCommand::Deployment { options }
+            | ScanCommand::StatefulSet { options }
+            | ScanCommand::DaemonSet { options }
+            | ScanCommand::Job { options } => options.chunk_size,
+            ScanCommand::Node { options } => options.chunk_size,
+        }
+    }
+
+    // Node subcommand selects nodes with its own --selector
+    pub fn node_selectors(&self) -> Option<String> {
+        match self {
+            ScanCommand::Pod { options, .. } | ScanCommand::All { options, .. } => {
+                options.node_selectors()
+            }
+            ScanCommand::Summary { options, .. } => options.node_selectors(),
+            ScanCommand::Deployment { options }
+            | ScanCommand::StatefulSet { options }
+            | ScanCommand::DaemonSet { options }
+            | ScanCommand::Job { options } => options.node_selectors(),
+            ScanCommand::Node { .. } => None,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CacheAction {
+    /// List nodes and write them to the node cache
+    Refresh,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum HistoryAction {
+    /// Append a snapshot of topology skew of all resources
+    Record {
+        #[command(flatten)]
+        options: Box<ResourceOptions>,
+
+        #[command(flatten)]
+        scope: Box<NamespaceScopeOptions>,
+
+        #[command(flatten)]
+        store: HistoryStoreOptions,
+    },
+    /// Print skew trends per workload from recorded snapshots
+    Show {
+        #[command(flatten)]
+        store: HistoryStoreOptions,
+
+        /// Number of latest snapshots to show
+        #[arg(long, default_value_t = DEFAULT_HISTORY_LAST, env = "KTS_HISTORY_LAST")]
+        last: usize,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum GenerateTarget {
+    /// Print the CronJob, ServiceAccount and RBAC writing a TopologySkewReport periodically
+    Manifests {
+        #[command(flatten)]
+        options: ManifestOptions,
+    },
 }
 
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
+pub struct ManifestOptions {
+    /// How the tool runs in-cluster
+    #[arg(long, value_enum, default_value_t = ManifestMode::Cronjob, env = "KTS_MANIFEST_MODE")]
+    pub mode: ManifestMode,
+
+    /// Container image including kubectl-topology_skew in its PATH
+    #[arg(long, env = "KTS_MANIFEST_IMAGE")]
+    pub image: String,
+
+    /// Namespace of the manifests and the report
+    #[arg(long, default_value = DEFAULT_MANIFEST_NAMESPACE, env = "KTS_MANIFEST_NAMESPACE")]
+    pub namespace: String,
+
+    /// Cron schedule of the CronJob
+    #[arg(long, default_value = DEFAULT_MANIFEST_SCHEDULE, env = "KTS_MANIFEST_SCHEDULE")]
+    pub schedule: String,
+
+    /// Name of the TopologySkewReport written by the job
+    #[arg(long, default_value = DEFAULT_REPORT_NAME, env = "KTS_MANIFEST_REPORT_NAME")]
+    pub report_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum, AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum ManifestMode {
+    /// Scan all namespaces on a schedule and write a TopologySkewReport
+    Cronjob,
+}
+
+impl Display for ManifestMode {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct HistoryStoreOptions {
+    /// Directory of the snapshot store, defaults to $XDG_STATE_HOME/kubectl-topology-skew/history
+    #[arg(long, env = "KTS_HISTORY_STORE")]
+    pub store: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct CompareOptions {
+    /// Kubernetes config context shown on the left
+    #[arg(long, env = "KTS_CONTEXT_A")]
+    pub context_a: String,
+
+    /// Kubernetes config context shown on the right
+    #[arg(long, env = "KTS_CONTEXT_B")]
+    pub context_b: String,
+}
+
+#[derive(Debug, Clone, Parser)]
 pub struct ResourceOptions {
     /// Kubernetes namespace name
-    #[arg(short, long, global = true)]
+    #[arg(short, long, global = true, env = "KTS_NAMESPACE")]
     pub namespace: Option<String>,
 
     /// Topology key
-    #[arg(short, long, default_value = DEFAULT_ZONE_LABEL)]
+    #[arg(short, long, default_value = DEFAULT_ZONE_LABEL, env = "KTS_TOPOLOGY_KEY")]
     pub topology_key: String,
 
     /// Label selector for pod list
-    #[arg(short = 'l', long, value_parser = parse_key_val)]
+    #[arg(short = 'l', long, value_parser = parse_key_val, env = "KTS_SELECTOR", value_delimiter = ',')]
     pub selector: Vec<Label>,
+
+    /// Label selector to restrict domains and pods to matching nodes, e.g. a nodepool
+    #[arg(long, value_parser = parse_key_val, env = "KTS_NODE_SELECTOR", value_delimiter = ',')]
+    pub node_selector: Vec<Label>,
+
+    /// Ignore the newest pods beyond spec.replicas of Deployments in a rollout
+    #[arg(long, env = "KTS_EXCLUDE_SURGE")]
+    pub exclude_surge: bool,
+
+    /// Return large lists in chunks rather than all at once. 0 disables chunking
+    #[arg(long, global = true, default_value_t = DEFAULT_CHUNK_SIZE, env = "KTS_CHUNK_SIZE")]
+    pub chunk_size: u32,
 }
 
 impl Default for ResourceOptions {
@@ -122,6 +582,9 @@ impl Default for ResourceOptions {
             namespace: None,
             topology_key: DEFAULT_ZONE_LABEL.to_string(),
             selector: Vec::new(),
+            node_selector: Vec::new(),
+            exclude_surge: false,
+            chunk_size: DEFAULT_CHUNK_SIZE,
         }
     }
 }
@@ -131,27 +594,219 @@ impl ResourceOptions {
         self.selector.selector()
     }
 
+    pub fn node_selectors(&self) -> Option<String> {
+        let s = self.node_selector.selector();
+        (!s.is_empty()).then_some(s)
+    }
+
     pub fn namespace(&self) -> Option<&str> {
         self.namespace.as_deref()
     }
 }
 
-#[derive(Debug, Parser)]
+// Same as ResourceOptions without the topology key, which is fixed to region and zone
+#[derive(Debug, Clone, Parser)]
+pub struct SummaryOptions {
+    /// Kubernetes namespace name
+    #[arg(short, long, global = true, env = "KTS_NAMESPACE")]
+    pub namespace: Option<String>,
+
+    /// Label selector for pod list
+    #[arg(short = 'l', long, value_parser = parse_key_val, env = "KTS_SELECTOR", value_delimiter = ',')]
+    pub selector: Vec<Label>,
+
+    /// Label selector to restrict domains and pods to matching nodes, e.g. a nodepool
+    #[arg(long, value_parser = parse_key_val, env = "KTS_NODE_SELECTOR", value_delimiter = ',')]
+    pub node_selector: Vec<Label>,
+
+    /// Ignore the newest pods beyond spec.replicas of Deployments in a rollout
+    #[arg(long, env = "KTS_EXCLUDE_SURGE")]
+    pub exclude_surge: bool,
+
+    /// Return large lists in chunks rather than all at once. 0 disables chunking
+    #[arg(long, global = true, default_value_t = DEFAULT_CHUNK_SIZE, env = "KTS_CHUNK_SIZE")]
+    pub chunk_size: u32,
+}
+
+impl Default for SummaryOptions {
+    fn default() -> Self {
+        Self {
+            namespace: None,
+            selector: Vec::new(),
+            node_selector: Vec::new(),
+            exclude_surge: false,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+}
+
+impl SummaryOptions {
+    pub fn node_selectors(&self) -> Option<String> {
+        let s = self.node_selector.selector();
+        (!s.is_empty()).then_some(s)
+    }
+
+    // The topology key is left to the default, the summary passes its own keys
+    pub fn resource_options(&self) -> ResourceOptions {
+        ResourceOptions {
+            namespace: self.namespace.clone(),
+            selector: self.selector.clone(),
+            node_selector: self.node_selector.clone(),
+            exclude_surge: self.exclude_surge,
+            chunk_size: self.chunk_size,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct NamespaceScopeOptions {
+    /// Scan resources in all namespaces
+    #[arg(
+        short = 'A',
+        long,
+        conflicts_with = "namespace",
+        env = "KTS_ALL_NAMESPACES"
+    )]
+    pub all_namespaces: bool,
+
+    /// Namespace name or regex to exclude from all namespaces scan
+    #[arg(long, requires = "all_namespaces", value_parser = parse_full_regex, env = "KTS_EXCLUDE_NAMESPACE")]
+    pub exclude_namespace: Vec<Regex>,
+
+    /// Exclude system namespaces such as kube-system from all namespaces scan
+    #[arg(long, requires = "all_namespaces", env = "KTS_SKIP_SYSTEM")]
+    pub skip_system: bool,
+
+    /// One section per value of this namespace label, e.g. team, with its worst workloads
+    #[arg(
+        long,
+        value_name = "KEY",
+        requires = "all_namespaces",
+        env = "KTS_AGGREGATE_BY_NAMESPACE_LABEL"
+    )]
+    pub aggregate_by_namespace_label: Option<String>,
+
+    /// Also print a combined table per value of this workload label, e.g. app.kubernetes.io/part-of
+    #[arg(long, value_name = "KEY", env = "KTS_AGGREGATE_BY_LABEL")]
+    pub aggregate_by_label: Option<String>,
+
+    /// Number of namespaces scanned concurrently
+    #[arg(long, default_value_t = DEFAULT_CONCURRENCY, env = "KTS_CONCURRENCY")]
+    pub concurrency: usize,
+
+    /// Path to a fleet file listing the clusters to scan
+    #[arg(long, conflicts_with_all = ["all_namespaces", "namespace", "contexts"], env = "KTS_FLEET")]
+    pub fleet: Option<PathBuf>,
+}
+
+impl Default for NamespaceScopeOptions {
+    fn default() -> Self {
+        Self {
+            all_namespaces: false,
+            exclude_namespace: Vec::new(),
+            skip_system: false,
+            aggregate_by_namespace_label: None,
+            aggregate_by_label: None,
+            concurrency: DEFAULT_CONCURRENCY,
+            fleet: None,
+        }
+    }
+}
+
+impl NamespaceScopeOptions {
+    pub fn is_excluded(&self, namespace: &str) -> bool {
+        let is_system = self.skip_system && SYSTEM_NAMESPACES.contains(&namespace);
+        is_system
+            || self
+                .exclude_namespace
+                .iter()
+                .any(|regex| regex.is_match(namespace))
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct PodFilterOptions {
+    /// Exclude pods owned by a DaemonSet, which are balanced by construction
+    #[arg(long, env = "KTS_EXCLUDE_DAEMONSETS")]
+    pub exclude_daemonsets: bool,
+
+    /// Include mirror pods of static pods such as control plane components
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, env = "KTS_INCLUDE_MIRROR_PODS")]
+    pub include_mirror_pods: bool,
+
+    /// Priority class name of pods to include. Can be repeated
+    #[arg(long, value_delimiter = ',', env = "KTS_PRIORITY_CLASS")]
+    pub priority_class: Vec<String>,
+
+    /// Annotation selector for pod list, filtered on the client since the API server cannot
+    #[arg(long, value_parser = parse_key_val, env = "KTS_ANNOTATION_SELECTOR", value_delimiter = ',')]
+    pub annotation_selector: Vec<Label>,
+
+    /// Show Running/Pending/Failed pod counts per domain
+    #[arg(long, env = "KTS_PHASE_COLUMNS")]
+    pub phase_columns: bool,
+
+    /// Show distinct nodes the pods occupy per domain, e.g. all pods of a zone on one node
+    #[arg(long, env = "KTS_NODE_COLUMN")]
+    pub node_column: bool,
+
+    /// Include only pods created within the duration, e.g. 1h
+    #[arg(long, value_parser = parse_duration, env = "KTS_NEWER_THAN")]
+    pub newer_than: Option<Duration>,
+
+    /// Include only pods created before the duration, e.g. 7d
+    #[arg(long, value_parser = parse_duration, env = "KTS_OLDER_THAN")]
+    pub older_than: Option<Duration>,
+}
+
+impl Default for PodFilterOptions {
+    fn default() -> Self {
+        Self {
+            exclude_daemonsets: false,
+            include_mirror_pods: true,
+            priority_class: Vec::new(),
+            annotation_selector: Vec::new(),
+            phase_columns: false,
+            node_column: false,
+            newer_than: None,
+            older_than: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
 pub struct ResourceWithNameOptions {
     /// Kubernetes namespace name
-    #[arg(short, long, global = true)]
+    #[arg(short, long, global = true, env = "KTS_NAMESPACE")]
     pub namespace: Option<String>,
 
     /// Topology key
-    #[arg(short, long, default_value = DEFAULT_ZONE_LABEL)]
+    #[arg(short, long, default_value = DEFAULT_ZONE_LABEL, env = "KTS_TOPOLOGY_KEY")]
     pub topology_key: String,
 
     /// Label selector for pod list
-    #[arg(short = 'l', long, value_parser = parse_key_val)]
+    #[arg(short = 'l', long, value_parser = parse_key_val, env = "KTS_SELECTOR", value_delimiter = ',')]
     pub selector: Vec<Label>,
 
+    /// Label selector to restrict domains and pods to matching nodes, e.g. a nodepool
+    #[arg(long, value_parser = parse_key_val, env = "KTS_NODE_SELECTOR", value_delimiter = ',')]
+    pub node_selector: Vec<Label>,
+
     /// Object name
     pub name: Option<String>,
+
+    /// Pick the object from a filterable list when no name is given
+    #[arg(long, conflicts_with = "name", env = "KTS_INTERACTIVE")]
+    pub interactive: bool,
+
+    /// Ignore the newest pods beyond spec.replicas of Deployments in a rollout
+    #[arg(long, env = "KTS_EXCLUDE_SURGE")]
+    pub exclude_surge: bool,
+
+    /// Return large lists in chunks rather than all at once. 0 disables chunking
+    #[arg(long, global = true, default_value_t = DEFAULT_CHUNK_SIZE, env = "KTS_CHUNK_SIZE")]
+    pub chunk_size: u32,
 }
 
 impl Default for ResourceWithNameOptions {
@@ -160,7 +815,11 @@ impl Default for ResourceWithNameOptions {
             namespace: None,
             topology_key: DEFAULT_ZONE_LABEL.to_string(),
             selector: Vec::new(),
+            node_selector: Vec::new(),
             name: None,
+            interactive: false,
+            exclude_surge: false,
+            chunk_size: DEFAULT_CHUNK_SIZE,
         }
     }
 }
@@ -172,6 +831,11 @@ impl ResourceWithNameOptions {
         (!s.is_empty()).then_some(s)
     }
 
+    pub fn node_selectors(&self) -> Option<String> {
+        let s = self.node_selector.selector();
+        (!s.is_empty()).then_some(s)
+    }
+
     pub fn namespace(&self) -> Option<&str> {
         self.namespace.as_deref()
     }
@@ -181,20 +845,50 @@ impl ResourceWithNameOptions {
     }
 }
 
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
 pub struct NodeOptions {
     /// Topology key
-    #[arg(short, long, default_value = DEFAULT_ZONE_LABEL)]
+    #[arg(short, long, default_value = DEFAULT_ZONE_LABEL, env = "KTS_TOPOLOGY_KEY")]
     pub topology_key: String,
 
     /// Label selector for pod list
-    #[arg(short = 'l', long, value_parser = parse_key_val)]
+    #[arg(short = 'l', long, value_parser = parse_key_val, env = "KTS_SELECTOR", value_delimiter = ',')]
     pub selector: Vec<Label>,
+
+    /// Group nodes by a preset of well-known labels, e.g. nodepool
+    #[arg(long, env = "KTS_PRESET")]
+    pub preset: Option<NodePreset>,
+
+    /// Show one table per kubelet version with nodes per domain
+    #[arg(long, conflicts_with = "preset", env = "KTS_SHOW_VERSION")]
+    pub show_version: bool,
+
+    /// Show one table per value of the node label with nodes per domain, e.g. kubernetes.io/arch
+    #[arg(long, conflicts_with_all = ["preset", "show_version"], env = "KTS_SPLIT_BY_NODE_LABEL")]
+    pub split_by_node_label: Option<String>,
+
+    /// Show one table per node role such as control-plane and worker with nodes per domain
+    #[arg(long, conflicts_with_all = ["preset", "show_version", "split_by_node_label"], env = "KTS_BY_ROLE")]
+    pub by_role: bool,
+
+    /// Show min/median/max node age per domain
+    #[arg(long, conflicts_with_all = ["preset", "show_version", "split_by_node_label", "by_role"], env = "KTS_SHOW_AGE")]
+    pub show_age: bool,
+
+    /// Show scheduled pods out of allocatable pod slots of the nodes per domain, e.g. 87/110
+    #[arg(long, conflicts_with_all = ["preset", "show_version", "split_by_node_label", "by_role"], env = "KTS_SHOW_CAPACITY")]
+    pub show_capacity: bool,
+
+    /// Return large lists in chunks rather than all at once. 0 disables chunking
+    #[arg(long, global = true, default_value_t = DEFAULT_CHUNK_SIZE, env = "KTS_CHUNK_SIZE")]
+    pub chunk_size: u32,
 }
 
 impl NodeOptions {
-    pub fn labels(&self) -> BTreeMap<String, String> {
-        self.selector.labels()
+    pub fn selectors(&self) -> Option<String> {
+        let s = self.selector.selector();
+        // empty string to None
+        (!s.is_empty()).then_some(s)
     }
 }
 
@@ -203,16 +897,40 @@ impl Default for NodeOptions {
         Self {
             topology_key: DEFAULT_ZONE_LABEL.to_string(),
             selector: Vec::new(),
+            preset: None,
+            show_version: false,
+            split_by_node_label: None,
+            by_role: false,
+            show_age: false,
+            show_capacity: false,
+            chunk_size: DEFAULT_CHUNK_SIZE,
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum, AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum NodePreset {
+    /// One table per nodepool of Karpenter or the cloud provider, with nodes per domain
+    Nodepool,
+}
+
+impl Display for NodePreset {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, ValueEnum, AsRefStr)]
 #[strum(serialize_all = "snake_case")]
 pub enum OutputFormat {
     Text,
     Yaml,
     Json,
+    /// One line per table, e.g. `apps/v1/deployment/web zone a=5 b=5 c=2 skew=3`
+    Summary,
+    /// One line per workload like kubectl, e.g. `deployment.apps/web skew=3`
+    Name,
 }
 
 impl Display for OutputFormat {
@@ -221,8 +939,221 @@ impl Display for OutputFormat {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum, AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum TableOrder {
+    Name,
+    Skew,
+}
+
+impl Display for TableOrder {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, ValueEnum, AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum OutputVersion {
+    /// Bare array of tables
+    #[default]
+    V1,
+    /// Object with apiVersion, kind, items, skipped and errors
+    V2,
+}
+
+impl Display for OutputVersion {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum, AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum DryRun {
+    /// Print the API calls without calling the API server
+    Client,
+}
+
+impl Display for DryRun {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum, AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum GroupOutput {
+    /// Workload kind, `other` for tables without a workload such as pod and node
+    Kind,
+}
+
+impl Display for GroupOutput {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, ValueEnum, AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum TableStyle {
+    #[default]
+    Blank,
+    Rounded,
+    Markdown,
+    Ascii,
+}
+
+impl Display for TableStyle {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum, AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl Display for LogFormat {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
 #[test]
 fn verify_cli() {
     use clap::CommandFactory;
     Args::command().debug_assert()
 }
+
+#[test]
+fn verbose_log_level() {
+    let log_level = |args: &[&str]| {
+        let args = [&["kubectl-topology_skew", "node"], args].concat();
+        Args::parse_from(args).log_level()
+    };
+
+    assert_eq!(log_level(&[]), log::LevelFilter::Error);
+    assert_eq!(log_level(&["-v"]), log::LevelFilter::Warn);
+    assert_eq!(log_level(&["-vvv"]), log::LevelFilter::Debug);
+    assert_eq!(log_level(&["-vvvvvv"]), log::LevelFilter::Trace);
+}
+
+// Walks the subcommands instead of setting variables, which would leak into parallel tests
+#[test]
+fn flags_have_env() {
+    use clap::CommandFactory;
+
+    fn missing_env(cmd: &clap::Command) -> Vec<String> {
+        let flags = cmd
+            .get_arguments()
+            .filter(|arg| !arg.is_positional() && arg.get_env().is_none())
+            .map(|arg| format!("{} --{}", cmd.get_name(), arg.get_id()))
+            .filter(|flag| !flag.ends_with("--help") && !flag.ends_with("--version"));
+        let nested = cmd
+            .get_subcommands()
+            .flat_map(missing_env)
+            .collect::<Vec<_>>();
+        flags.chain(nested).collect()
+    }
+
+    let mut cmd = Args::command();
+    cmd.build();
+    assert_eq!(missing_env(&cmd), Vec::<String>::new());
+}
+
+#[test]
+fn namespace_scope_is_excluded() {
+    let args = Args::parse_from([
+        "kubectl-topology_skew",
+        "all",
+        "-A",
+        "--skip-system",
+        "--exclude-namespace",
+        "team-.*",
+        "--exclude-namespace",
+        "default",
+    ]);
+    let Some(SubCommand::Scan(ScanCommand::All { scope, .. })) = args.sub else {
+        panic!("unexpected subcommand");
+    };
+
+    assert!(scope.is_excluded("kube-system"));
+    assert!(scope.is_excluded("team-a"));
+    assert!(scope.is_excluded("default"));
+    assert!(!scope.is_excluded("default2"));
+    assert!(!scope.is_excluded("prod"));
+}
+
+#[test]
+fn fleet_conflicts_with_contexts() {
+    let args = ["kubectl-topology_skew", "all", "--fleet", "fleet.yaml"];
+    assert!(Args::try_parse_from(args).is_ok());
+
+    let args = [&args[..], &["--contexts", "prod-*"]].concat();
+    assert!(Args::try_parse_from(args).is_err());
+}
+
+#[test]
+fn report_cr_conflicts_with_contexts() {
+    let args = ["kubectl-topology_skew", "--write-report-cr", "all"];
+    assert!(Args::try_parse_from(args).is_ok());
+
+    let args = [&args[..], &["--contexts", "prod-*"]].concat();
+    assert!(Args::try_parse_from(args).is_err());
+}
+
+#[test]
+fn selector_with_inequality() {
+    let args = Args::parse_from(["kubectl-topology_skew", "pod", "-l", "app=web,tier!=db"]);
+    let Some(SubCommand::Scan(ScanCommand::Pod { options, .. })) = args.sub else {
+        panic!("pod subcommand is expected");
+    };
+    assert_eq!(options.selectors(), "app=web,tier!=db");
+}
+
+#[test]
+fn selector_with_existence() {
+    let args = Args::parse_from(["kubectl-topology_skew", "pod", "-l", "app,!debug"]);
+    let Some(SubCommand::Scan(ScanCommand::Pod { options, .. })) = args.sub else {
+        panic!("pod subcommand is expected");
+    };
+    assert_eq!(options.selectors(), "app,!debug");
+
+    assert!(Args::try_parse_from(["kubectl-topology_skew", "pod", "-l", "!"]).is_err());
+}
+
+#[test]
+fn compare_takes_scan_subcommand() {
+    let args = Args::parse_from([
+        "kubectl-topology_skew",
+        "compare",
+        "--context-a",
+        "staging",
+        "--context-b",
+        "prod",
+        "deploy",
+        "my-app",
+    ]);
+    let Some(SubCommand::Compare {
+        target: ScanCommand::Deployment { options },
+        ..
+    }) = args.sub
+    else {
+        panic!("compare of a deployment is expected");
+    };
+    assert_eq!(options.name(), Some("my-app"));
+
+    let args = [
+        "kubectl-topology_skew",
+        "compare",
+        "--context-a",
+        "a",
+        "--context-b",
+        "b",
+    ];
+    assert!(Args::try_parse_from([&args[..], &["history", "show"]].concat()).is_err());
+}
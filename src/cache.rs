@@ -0,0 +1,130 @@
+use std::{
+    fs, io,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::*;
+use k8s_openapi::api::core::v1::Node;
+use kube::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::CachedNodeApi;
+
+// The time of the list is recorded in the file rather than taken from its modified time,
+// which is reset when the file is copied
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NodeCache {
+    // URL of the API server the nodes were listed from
+    server: String,
+    fetched_at: String,
+    nodes: Vec<Node>,
+}
+
+// A cache of another cluster, e.g. after switching the context, is a miss as well as an expired one
+pub fn load(path: &Path, server: &str, ttl: Duration) -> Result<Option<Vec<Node>>> {
+    let json = match fs::read_to_string(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        json => json.with_context(|| format!("Fail to read node cache: {}", path.display()))?,
+    };
+    let cache: NodeCache = serde_json::from_str(&json)
+        .with_context(|| format!("Fail to parse node cache: {}", path.display()))?;
+
+    if cache.server != server {
+        log::info!("Node cache is of another cluster: {}", path.display());
+        return Ok(None);
+    }
+
+    let fetched_at = humantime::parse_rfc3339(&cache.fetched_at)
+        .with_context(|| format!("Fail to parse node cache: {}", path.display()))?;
+    let age = SystemTime::now()
+        .duration_since(fetched_at)
+        .unwrap_or_default();
+    if age >= ttl {
+        log::info!("Node cache is expired: {}", path.display());
+        return Ok(None);
+    }
+
+    Ok(Some(cache.nodes))
+}
+
+pub fn save(path: &Path, server: &str, nodes: &[Node]) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Fail to create node cache dir: {}", dir.display()))?;
+    }
+    let cache = NodeCache {
+        server: server.to_string(),
+        fetched_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        nodes: nodes.to_vec(),
+    };
+    fs::write(path, serde_json::to_string(&cache)?)
+        .with_context(|| format!("Fail to write node cache: {}", path.display()))
+}
+
+// Nodes are listed only when the cache is missing, expired or of another cluster
+pub async fn node_api(
+    cli: Client,
+    chunk_size: u32,
+    path: &Path,
+    server: &str,
+    ttl: Duration,
+) -> Result<CachedNodeApi> {
+    if let Some(nodes) = load(path, server, ttl)? {
+        return Ok(CachedNodeApi::from_nodes(nodes));
+    }
+    refresh(cli, chunk_size, path, server).await
+}
+
+// Nodes fetched one by one without the list permission are not cached, since they are a part
+pub async fn refresh(
+    cli: Client,
+    chunk_size: u32,
+    path: &Path,
+    server: &str,
+) -> Result<CachedNodeApi> {
+    let node_api = CachedNodeApi::try_from(cli, chunk_size).await?;
+    if !node_api.is_degraded() {
+        save(path, server, &node_api.nodes())?;
+    }
+    Ok(node_api)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use kube::api::ObjectMeta;
+
+    use super::*;
+
+    #[test]
+    fn save_and_load_with_ttl() -> Result<()> {
+        let path = env::temp_dir().join("kubectl-topology-skew-cache/nodes.json");
+        let server = "https://cluster-a.example.com/";
+        let _ = fs::remove_file(&path);
+        assert_eq!(load(&path, server, Duration::from_secs(600))?, None);
+
+        let node = Node {
+            metadata: ObjectMeta {
+                name: Some(String::from("node1")),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        save(&path, server, std::slice::from_ref(&node))?;
+        assert_eq!(
+            load(&path, server, Duration::from_secs(600))?,
+            Some(vec![node])
+        );
+        assert_eq!(load(&path, server, Duration::ZERO)?, None);
+
+        // Another cluster doesn't reuse the nodes
+        let other = "https://cluster-b.example.com/";
+        assert_eq!(load(&path, other, Duration::from_secs(600))?, None);
+
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+}
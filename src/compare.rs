@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{Topology, TopologyTable, TopologyTables};
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct CompareTable {
+    pub contexts: (String, String),
+    pub rows: Vec<CompareRow>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct CompareRow {
+    pub key: String,
+
+    // None if the domain or the workload doesn't exist in the context
+    pub a: Option<Counts>,
+    pub b: Option<Counts>,
+    pub diff: bool,
+}
+
+#[derive(Debug, Default, Serialize, PartialEq, Eq, Clone, Copy)]
+pub struct Counts {
+    pub count: u32,
+    pub skew: u32,
+}
+
+impl From<&Topology> for Counts {
+    fn from(topology: &Topology) -> Self {
+        Counts {
+            count: topology.count,
+            skew: topology.skew,
+        }
+    }
+}
+
+impl CompareTable {
+    // Worse of the two contexts, to order the tables by skew
+    pub fn max_skew(&self) -> u32 {
+        self.rows
+            .iter()
+            .flat_map(|row| [row.a, row.b])
+            .flatten()
+            .map(|counts| counts.skew)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+// Tables of the same workload are joined by the header,
+// and rows of the same domain are joined by the topology key
+pub fn compare_tables(
+    (context_a, tables_a): (&str, TopologyTables),
+    (context_b, tables_b): (&str, TopologyTables),
+) -> Vec<CompareTable> {
+    let mut joined: BTreeMap<Option<String>, (Option<TopologyTable>, Option<TopologyTable>)> =
+        BTreeMap::new();
+
+    for table in tables_a {
+        let header = table.header.clone();
+        joined.entry(header).or_default().0 = Some(table);
+    }
+    for table in tables_b {
+        let header = table.header.clone();
+        joined.entry(header).or_default().1 = Some(table);
+    }
+
+    let compare = |(header, (a, b)): (
+        Option<String>,
+        (Option<TopologyTable>, Option<TopologyTable>),
+    )| {
+        let mut rows: BTreeMap<String, (Option<Counts>, Option<Counts>)> = BTreeMap::new();
+
+        for topology in a.iter().flat_map(|table| &table.topologies) {
+            rows.entry(topology.key.clone()).or_default().0 = Some(topology.into());
+        }
+        for topology in b.iter().flat_map(|table| &table.topologies) {
+            rows.entry(topology.key.clone()).or_default().1 = Some(topology.into());
+        }
+
+        let rows = rows
+            .into_iter()
+            .map(|(key, (a, b))| CompareRow {
+                key,
+                a,
+                b,
+                diff: a != b,
+            })
+            .collect();
+
+        CompareTable {
+            contexts: (context_a.to_string(), context_b.to_string()),
+            rows,
+            header,
+        }
+    };
+
+    joined.into_iter().map(compare).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeSet, HashSet};
+
+    fn tables(values: &[&str]) -> TopologyTables {
+        let domains = HashSet::from([String::from("zone-a"), String::from("zone-b")]);
+        let topology_values = values.iter().map(ToString::to_string).collect();
+        let table = TopologyTable::create(topology_values, &domains, Some(String::from("app")));
+
+        TopologyTables::from(BTreeSet::from([table]))
+    }
+
+    #[test]
+    fn compare_tables_diff() {
+        let a = tables(&["zone-a", "zone-b"]);
+        let b = tables(&["zone-a", "zone-a", "zone-b"]);
+
+        let compared = compare_tables(("staging", a), ("prod", b));
+        assert_eq!(compared.len(), 1);
+
+        let table = &compared[0];
+        assert_eq!(table.header.as_deref(), Some("app"));
+        assert_eq!(
+            table.contexts,
+            (String::from("staging"), String::from("prod"))
+        );
+
+        let zone_a = &table.rows[0];
+        assert_eq!(zone_a.key, "zone-a");
+        assert_eq!(zone_a.a, Some(Counts { count: 1, skew: 0 }));
+        assert_eq!(zone_a.b, Some(Counts { count: 2, skew: 1 }));
+        assert!(zone_a.diff);
+
+        let zone_b = &table.rows[1];
+        assert_eq!(zone_b.a, zone_b.b);
+        assert!(!zone_b.diff);
+
+        assert_eq!(table.max_skew(), 1);
+    }
+}
@@ -5,9 +5,16 @@ use itertools::*;
 use k8s_openapi::api::apps::v1::DaemonSet;
 use kube::{api::TypeMeta, Client, ResourceExt};
 
-use crate::{arg::ResourceWithNameOptions, resources, topology_table_find_by, TopologyTables};
-
-pub async fn daemonset(opts: ResourceWithNameOptions, cli: Client) -> Result<TopologyTables> {
+use crate::{
+    arg::ResourceWithNameOptions, pick_resources, resources, topology_table_find_by, CachedNodeApi,
+    NotFound, TopologyTables, Workload,
+};
+
+pub async fn daemonset(
+    opts: ResourceWithNameOptions,
+    cli: Client,
+    node_api: &CachedNodeApi,
+) -> Result<TopologyTables> {
     let name = opts.name();
     let namespace = opts.namespace().unwrap_or(cli.default_namespace());
     let selectors = opts.selectors();
@@ -15,16 +22,24 @@ pub async fn daemonset(opts: ResourceWithNameOptions, cli: Client) -> Result<Top
         resources::<DaemonSet>(name, namespace, selectors.as_deref(), cli.clone()).await?;
 
     if daemonsets.is_empty() {
-        bail!("No found daemonset");
+        bail!(NotFound("daemonset"));
     }
 
-    let labels_map = labels_set_by(&daemonsets)?;
+    let daemonsets = if opts.interactive {
+        pick_resources(daemonsets)?
+    } else {
+        daemonsets
+    };
+
+    let workloads = labels_set_by(&daemonsets)?;
     let topology_key = &opts.topology_key;
     let tables = topology_table_find_by(
-        labels_map,
+        workloads,
         namespace,
         topology_key,
+        opts.chunk_size,
         cli.clone(),
+        node_api,
         name.is_none(),
     )
     .await?;
@@ -32,7 +47,7 @@ pub async fn daemonset(opts: ResourceWithNameOptions, cli: Client) -> Result<Top
     Ok(tables)
 }
 
-pub fn labels_set_by(daemonsets: &[DaemonSet]) -> Result<BTreeMap<String, String>> {
+pub fn labels_set_by(daemonsets: &[DaemonSet]) -> Result<BTreeMap<String, Workload>> {
     let ds_to_labels = |ds: &DaemonSet| {
         let selector = ds
             .spec
@@ -50,7 +65,9 @@ pub fn labels_set_by(daemonsets: &[DaemonSet]) -> Result<BTreeMap<String, String
         let api_version = meta.api_version;
         let kind = meta.kind.to_lowercase();
         let name = format!("{}/{}/{}", api_version, kind, ds.name_any());
-        Ok((name, labels))
+        let template = ds.spec.as_ref().map(|spec| &spec.template);
+        let workload = Workload::new(labels, template).with_labels(ds.labels());
+        Ok((name, workload))
     };
 
     let labels = daemonsets
@@ -83,10 +100,9 @@ mod tests {
         let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
         let spawned = tokio::spawn(async move {
             pin_mut!(handle);
-            create_objects!(handle, "../tests/ds_no_options_ds.yaml", DaemonSet);
             create_objects!(handle, "../tests/nodes.yaml", Node);
-            create_objects!(handle, "../tests/ds_no_options_pods1.yaml", Pod);
-            create_objects!(handle, "../tests/ds_no_options_pods2.yaml", Pod);
+            create_objects!(handle, "../tests/ds_no_options_ds.yaml", DaemonSet);
+            create_objects!(handle, "../tests/ds_no_options_pods.yaml", Pod);
 
             Ok(())
         });
@@ -98,7 +114,8 @@ mod tests {
             ..Default::default()
         };
 
-        let topology_tables = daemonset(opts, cli).await?;
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = daemonset(opts, cli, &node_api).await?;
 
         let mut topology_table_iter = topology_tables.into_iter();
 
@@ -158,6 +175,7 @@ mod tests {
         let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
         let spawned = tokio::spawn(async move {
             pin_mut!(handle);
+            create_objects!(handle, "../tests/nodes.yaml", Node);
             create_objects!(handle, "../tests/empty.yaml", DaemonSet);
             Ok(())
         });
@@ -169,7 +187,8 @@ mod tests {
             ..Default::default()
         };
 
-        let result = daemonset(opts, cli).await;
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let result = daemonset(opts, cli, &node_api).await;
         // TODO
         assert!(result.is_err());
 
@@ -5,9 +5,16 @@ use itertools::*;
 use k8s_openapi::api::apps::v1::Deployment;
 use kube::{api::TypeMeta, Client, ResourceExt};
 
-use crate::{arg::ResourceWithNameOptions, resources, topology_table_find_by, TopologyTables};
-
-pub async fn deployment(opts: ResourceWithNameOptions, cli: Client) -> Result<TopologyTables> {
+use crate::{
+    arg::ResourceWithNameOptions, pick_resources, resources, topology_table_find_by, CachedNodeApi,
+    NotFound, TopologyTables, Workload,
+};
+
+pub async fn deployment(
+    opts: ResourceWithNameOptions,
+    cli: Client,
+    node_api: &CachedNodeApi,
+) -> Result<TopologyTables> {
     let name = opts.name();
     let namespace = opts.namespace().unwrap_or(cli.default_namespace());
     let selectors = opts.selectors();
@@ -15,16 +22,27 @@ pub async fn deployment(opts: ResourceWithNameOptions, cli: Client) -> Result<To
         resources::<Deployment>(name, namespace, selectors.as_deref(), cli.clone()).await?;
 
     if deployments.is_empty() {
-        bail!("No found deployments");
+        bail!(NotFound("deployments"));
     }
 
-    let labels_map = labels_set_by(&deployments)?;
+    let deployments = if opts.interactive {
+        pick_resources(deployments)?
+    } else {
+        deployments
+    };
+
+    let mut workloads = labels_set_by(&deployments)?;
+    if opts.exclude_surge {
+        limit_surge(&mut workloads, &deployments);
+    }
     let topology_key = &opts.topology_key;
     let tables = topology_table_find_by(
-        labels_map,
+        workloads,
         namespace,
         topology_key,
+        opts.chunk_size,
         cli.clone(),
+        node_api,
         name.is_none(),
     )
     .await?;
@@ -32,7 +50,7 @@ pub async fn deployment(opts: ResourceWithNameOptions, cli: Client) -> Result<To
     Ok(tables)
 }
 
-pub fn labels_set_by(deployments: &[Deployment]) -> Result<BTreeMap<String, String>> {
+pub fn labels_set_by(deployments: &[Deployment]) -> Result<BTreeMap<String, Workload>> {
     let deploy_to_labels = |deploy: &Deployment| {
         let selector = deploy
             .spec
@@ -46,12 +64,14 @@ pub fn labels_set_by(deployments: &[Deployment]) -> Result<BTreeMap<String, Stri
             .map(|x| x.iter().map(|(k, v)| format!("{}={}", k, v)).join(","))
             .context("No found selector")?;
 
-        let meta = TypeMeta::resource::<Deployment>();
-        let api_version = meta.api_version;
-        let kind = meta.kind.to_lowercase();
-        let name = format!("{}/{}/{}", api_version, kind, deploy.name_any());
+        let name = workload_name(deploy);
+
+        let template = deploy.spec.as_ref().map(|spec| &spec.template);
+        let workload = Workload::new(labels, template)
+            .with_annotations(deploy.annotations())
+            .with_labels(deploy.labels());
 
-        Ok((name, labels))
+        Ok((name, workload))
     };
 
     let labels = deployments
@@ -62,6 +82,30 @@ pub fn labels_set_by(deployments: &[Deployment]) -> Result<BTreeMap<String, Stri
     Ok(labels)
 }
 
+// During a rollout, pods beyond spec.replicas are the surge that will be scaled down
+pub fn limit_surge(workloads: &mut BTreeMap<String, Workload>, deployments: &[Deployment]) {
+    for deploy in deployments {
+        let desired = deploy.spec.as_ref().and_then(|spec| spec.replicas);
+        let current = deploy.status.as_ref().and_then(|status| status.replicas);
+        let (Some(desired), Some(current)) = (desired, current) else {
+            continue;
+        };
+        if current <= desired {
+            continue;
+        }
+        if let Some(workload) = workloads.get_mut(&workload_name(deploy)) {
+            workload.surge_limit = u32::try_from(desired).ok();
+        }
+    }
+}
+
+fn workload_name(deploy: &Deployment) -> String {
+    let meta = TypeMeta::resource::<Deployment>();
+    let api_version = meta.api_version;
+    let kind = meta.kind.to_lowercase();
+    format!("{}/{}/{}", api_version, kind, deploy.name_any())
+}
+
 #[cfg(test)]
 mod tests {
     use k8s_openapi::api::core::v1::{Node, Pod};
@@ -71,7 +115,7 @@ mod tests {
     };
     use serde::Deserialize;
 
-    use crate::kube::tests::create_objects;
+    use crate::kube::tests::{create_objects, send_status};
 
     use super::*;
     use futures::pin_mut;
@@ -79,15 +123,117 @@ mod tests {
     use kube::client::Body;
     use tower_test::mock;
 
+    #[tokio::test]
+    async fn deploy_exclude_surge() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/nodes.yaml", Node);
+            create_objects!(handle, "../tests/deploy_surge_deploy.yaml", Deployment);
+            create_objects!(handle, "../tests/deploy_surge_pods.yaml", Pod);
+
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceWithNameOptions {
+            namespace: Some(ns.to_owned()),
+            exclude_surge: true,
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = deployment(opts, cli, &node_api).await?;
+        spawned.await??;
+
+        // The newest pod on node1 is the surge
+        let topology_table = topology_tables.into_iter().next().unwrap();
+        let counts = topology_table
+            .topologies
+            .into_iter()
+            .map(|topology| topology.count)
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![1, 1, 0]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deploy_min_domains_finding() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/node_cordoned_nodes.yaml", Node);
+            create_objects!(
+                handle,
+                "../tests/deploy_min_domains_deploy.yaml",
+                Deployment
+            );
+            create_objects!(handle, "../tests/deploy_min_domains_pods.yaml", Pod);
+
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceWithNameOptions {
+            namespace: Some(ns.to_owned()),
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = deployment(opts, cli, &node_api).await?;
+        spawned.await??;
+
+        assert_eq!(
+            topology_tables.findings,
+            vec![
+                "apps/v1/deployment/web: 1 eligible domains of topology.kubernetes.io/zone \
+                  are fewer than minDomains 3, ineligible: asia-northeast1-b (tainted)"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deploy_named_not_found() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/nodes.yaml", Node);
+            send_status!(handle, 404);
+
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceWithNameOptions {
+            namespace: Some(ns.to_owned()),
+            name: Some(String::from("foo")),
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let result = deployment(opts, cli, &node_api).await;
+        spawned.await??;
+
+        // Prints nothing with --ignore-not-found, while an empty list prints an empty table
+        assert!(result.is_err_and(|e| crate::is_not_found(&e)));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn deploy_no_options() -> Result<()> {
         let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
         let spawned = tokio::spawn(async move {
             pin_mut!(handle);
-            create_objects!(handle, "../tests/deploy_no_options_deploy.yaml", Deployment);
             create_objects!(handle, "../tests/nodes.yaml", Node);
-            create_objects!(handle, "../tests/deploy_no_options_pods1.yaml", Pod);
-            create_objects!(handle, "../tests/deploy_no_options_pods2.yaml", Pod);
+            create_objects!(handle, "../tests/deploy_no_options_deploy.yaml", Deployment);
+            create_objects!(handle, "../tests/deploy_no_options_pods.yaml", Pod);
 
             Ok(())
         });
@@ -99,7 +245,8 @@ mod tests {
             ..Default::default()
         };
 
-        let topology_tables = deployment(opts, cli).await?;
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = deployment(opts, cli, &node_api).await?;
 
         let mut topology_table_iter = topology_tables.into_iter();
 
@@ -153,4 +300,78 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn deploy_max_skew() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/nodes.yaml", Node);
+            create_objects!(handle, "../tests/deploy_max_skew_deploy.yaml", Deployment);
+            create_objects!(handle, "../tests/deploy_no_options_pods.yaml", Pod);
+
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceWithNameOptions {
+            namespace: Some(ns.to_owned()),
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = deployment(opts, cli, &node_api).await?;
+
+        let mut topology_table_iter = topology_tables.into_iter();
+
+        // deploy1 declares maxSkew for the zone key
+        let topology_table1 = topology_table_iter.next().unwrap();
+        for topology in topology_table1.topologies {
+            assert_eq!(topology.allowed, Some(1));
+        }
+
+        // deploy2 declares maxSkew only for the hostname key
+        let topology_table2 = topology_table_iter.next().unwrap();
+        for topology in topology_table2.topologies {
+            assert_eq!(topology.allowed, None);
+        }
+
+        spawned.await??;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deploy_skip_without_pods() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/nodes.yaml", Node);
+            create_objects!(handle, "../tests/deploy_max_skew_deploy.yaml", Deployment);
+            create_objects!(handle, "../tests/deploy_one_running_pods.yaml", Pod);
+
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceWithNameOptions {
+            namespace: Some(ns.to_owned()),
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = deployment(opts, cli, &node_api).await?;
+        spawned.await??;
+
+        // deploy2 has no running pods
+        let headers = topology_tables
+            .iter()
+            .filter_map(|table| table.header.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(headers, vec!["apps/v1/deployment/deploy1"]);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,123 @@
+use std::path::Path;
+
+use anyhow::*;
+use serde::Deserialize;
+
+use crate::arg::{parse_full_regex, NamespaceScopeOptions, ResourceOptions};
+
+// Scan scope of clusters managed declaratively instead of long flag lists
+//
+// clusters:
+// - context: prod-us
+//   excludeNamespaces: ["team-.*"]
+//   skipSystem: true
+//   minSkew: 1
+// - context: staging
+//   namespace: default
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct Fleet {
+    pub clusters: Vec<FleetCluster>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct FleetCluster {
+    pub context: String,
+
+    // All namespaces are scanned if not given
+    #[serde(default)]
+    pub namespace: Option<String>,
+
+    #[serde(default)]
+    pub exclude_namespaces: Vec<String>,
+
+    #[serde(default)]
+    pub skip_system: bool,
+
+    #[serde(default)]
+    pub min_skew: Option<u32>,
+}
+
+impl Fleet {
+    pub fn read(path: &Path) -> Result<Self> {
+        let yaml = std::fs::read_to_string(path)
+            .with_context(|| format!("Fail to read fleet file: {}", path.display()))?;
+        let fleet: Fleet = serde_yaml::from_str(&yaml)
+            .with_context(|| format!("Fail to parse fleet file: {}", path.display()))?;
+
+        if fleet.clusters.is_empty() {
+            bail!("No found clusters in fleet file: {}", path.display());
+        }
+
+        Ok(fleet)
+    }
+}
+
+impl FleetCluster {
+    // Options of the all subcommand overridden by the cluster entry
+    pub fn all_options(
+        &self,
+        opts: &ResourceOptions,
+        scope: &NamespaceScopeOptions,
+    ) -> Result<(ResourceOptions, NamespaceScopeOptions)> {
+        let exclude_namespace = self
+            .exclude_namespaces
+            .iter()
+            .map(|pattern| parse_full_regex(pattern))
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("Fail to parse excludeNamespaces: {}", self.context))?;
+
+        let opts = ResourceOptions {
+            namespace: self.namespace.clone(),
+            ..opts.clone()
+        };
+        let scope = NamespaceScopeOptions {
+            all_namespaces: self.namespace.is_none(),
+            exclude_namespace,
+            skip_system: self.skip_system,
+            fleet: None,
+            ..scope.clone()
+        };
+
+        Ok((opts, scope))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fleet_all_options() -> Result<()> {
+        let yaml = r#"
+clusters:
+- context: prod-us
+  excludeNamespaces: ["team-.*"]
+  skipSystem: true
+  minSkew: 1
+- context: staging
+  namespace: default
+"#;
+        let fleet: Fleet = serde_yaml::from_str(yaml)?;
+        assert_eq!(fleet.clusters.len(), 2);
+
+        let opts = ResourceOptions::default();
+        let scope = NamespaceScopeOptions::default();
+
+        let prod = &fleet.clusters[0];
+        let (prod_opts, prod_scope) = prod.all_options(&opts, &scope)?;
+        assert_eq!(prod.min_skew, Some(1));
+        assert!(prod_opts.namespace.is_none());
+        assert!(prod_scope.all_namespaces);
+        assert!(prod_scope.is_excluded("kube-system"));
+        assert!(prod_scope.is_excluded("team-a"));
+
+        let (staging_opts, staging_scope) = fleet.clusters[1].all_options(&opts, &scope)?;
+        assert_eq!(staging_opts.namespace.as_deref(), Some("default"));
+        assert!(!staging_scope.all_namespaces);
+
+        assert!(serde_yaml::from_str::<Fleet>("clusters:\n- context: a\n  unknown: 1\n").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use tabled::Tabled;
+
+use crate::TopologyTables;
+
+const STORE_DIR: &str = "kubectl-topology-skew/history";
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+// A snapshot is stored as one JSON file per record, named by the unix time to keep the order
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Snapshot {
+    pub time: String,
+    pub workloads: BTreeMap<String, WorkloadSkew>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub struct WorkloadSkew {
+    pub max_skew: u32,
+    pub total: u32,
+}
+
+#[derive(Debug, Tabled, Serialize, PartialEq)]
+#[tabled(rename_all = "UPPERCASE")]
+pub struct Trend {
+    pub workload: String,
+    pub skew: u32,
+    pub max: u32,
+    pub trend: String,
+}
+
+impl Snapshot {
+    pub fn create(tables: &TopologyTables, time: SystemTime) -> Self {
+        let workloads = tables
+            .iter()
+            .map(|table| {
+                let name = table.header.clone().unwrap_or_default();
+                let skew = WorkloadSkew {
+                    max_skew: table.max_skew(),
+                    total: table.total(),
+                };
+                (name, skew)
+            })
+            .collect();
+
+        Snapshot {
+            time: humantime::format_rfc3339_seconds(time).to_string(),
+            workloads,
+        }
+    }
+}
+
+pub fn store_dir(store: Option<&Path>) -> Result<PathBuf> {
+    if let Some(store) = store {
+        return Ok(store.to_path_buf());
+    }
+
+    let state_home = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".local/state"))
+            .context("No found HOME for the history store")?,
+    };
+
+    Ok(state_home.join(STORE_DIR))
+}
+
+pub fn record(dir: &Path, tables: &TopologyTables) -> Result<PathBuf> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Fail to create history store: {}", dir.display()))?;
+
+    let now = SystemTime::now();
+    let secs = now.duration_since(UNIX_EPOCH)?.as_secs();
+    let path = dir.join(format!("{secs}.json"));
+
+    let snapshot = Snapshot::create(tables, now);
+    fs::write(&path, serde_json::to_vec(&snapshot)?)
+        .with_context(|| format!("Fail to write snapshot: {}", path.display()))?;
+
+    Ok(path)
+}
+
+pub fn load(dir: &Path, last: usize) -> Result<Vec<Snapshot>> {
+    let entries = fs::read_dir(dir)
+        .with_context(|| format!("Fail to read history store: {}", dir.display()))?;
+
+    let mut paths = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect::<Vec<_>>();
+    paths.sort_by_key(|path| {
+        path.file_stem()
+            .and_then(|s| s.to_str()?.parse::<u64>().ok())
+    });
+
+    let skip = paths.len().saturating_sub(last);
+    paths
+        .iter()
+        .skip(skip)
+        .map(|path| {
+            let json = fs::read(path)
+                .with_context(|| format!("Fail to read snapshot: {}", path.display()))?;
+            serde_json::from_slice(&json)
+                .with_context(|| format!("Fail to parse snapshot: {}", path.display()))
+        })
+        .collect()
+}
+
+// Max skew of each workload over the snapshots, oldest first
+pub fn trends(snapshots: &[Snapshot]) -> Vec<Trend> {
+    let mut skews: BTreeMap<&str, Vec<Option<u32>>> = BTreeMap::new();
+
+    for (i, snapshot) in snapshots.iter().enumerate() {
+        for (name, workload) in &snapshot.workloads {
+            let skews = skews
+                .entry(name)
+                .or_insert_with(|| vec![None; snapshots.len()]);
+            skews[i] = Some(workload.max_skew);
+        }
+    }
+
+    let trend = |(name, skews): (&str, Vec<Option<u32>>)| {
+        let max = skews.iter().flatten().max().copied().unwrap_or_default();
+        Trend {
+            workload: name.to_string(),
+            skew: skews
+                .iter()
+                .rev()
+                .flatten()
+                .next()
+                .copied()
+                .unwrap_or_default(),
+            max,
+            trend: sparkline(&skews, max),
+        }
+    };
+
+    skews.into_iter().map(trend).collect()
+}
+
+// Missing snapshots of the workload are shown as a space
+fn sparkline(skews: &[Option<u32>], max: u32) -> String {
+    let spark = |skew: &Option<u32>| match skew {
+        Some(skew) if max > 0 => SPARKS[(*skew as usize * (SPARKS.len() - 1)) / max as usize],
+        Some(_) => SPARKS[0],
+        None => ' ',
+    };
+
+    skews.iter().map(spark).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(workloads: &[(&str, u32)]) -> Snapshot {
+        let workloads = workloads
+            .iter()
+            .map(|(name, max_skew)| {
+                let skew = WorkloadSkew {
+                    max_skew: *max_skew,
+                    total: 3,
+                };
+                (name.to_string(), skew)
+            })
+            .collect();
+
+        Snapshot {
+            time: String::new(),
+            workloads,
+        }
+    }
+
+    #[test]
+    fn trends_sparkline() {
+        let snapshots = vec![
+            snapshot(&[("a", 0), ("b", 1)]),
+            snapshot(&[("a", 2)]),
+            snapshot(&[("a", 4), ("b", 1)]),
+        ];
+
+        let trends = trends(&snapshots);
+        assert_eq!(trends.len(), 2);
+
+        assert_eq!(trends[0].workload, "a");
+        assert_eq!(trends[0].skew, 4);
+        assert_eq!(trends[0].max, 4);
+        assert_eq!(trends[0].trend, "▁▄█");
+
+        assert_eq!(trends[1].workload, "b");
+        assert_eq!(trends[1].trend, "█ █");
+    }
+
+    #[test]
+    fn record_and_load() -> Result<()> {
+        let dir = env::temp_dir().join("kubectl-topology-skew-history");
+        let _ = fs::remove_dir_all(&dir);
+
+        let path = record(&dir, &TopologyTables::default())?;
+        assert!(path.exists());
+        fs::write(
+            dir.join("1.json"),
+            serde_json::to_vec(&snapshot(&[("a", 1)]))?,
+        )?;
+
+        let snapshots = load(&dir, 10)?;
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0], snapshot(&[("a", 1)]));
+
+        assert_eq!(load(&dir, 1)?.len(), 1);
+
+        fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+}
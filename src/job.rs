@@ -5,25 +5,40 @@ use itertools::*;
 use k8s_openapi::api::batch::v1::Job;
 use kube::{api::TypeMeta, Client, ResourceExt};
 
-use crate::{arg::ResourceWithNameOptions, resources, topology_table_find_by, TopologyTables};
-
-pub async fn job(opts: ResourceWithNameOptions, cli: Client) -> Result<TopologyTables> {
+use crate::{
+    arg::ResourceWithNameOptions, pick_resources, resources, topology_table_find_by, CachedNodeApi,
+    NotFound, TopologyTables, Workload,
+};
+
+pub async fn job(
+    opts: ResourceWithNameOptions,
+    cli: Client,
+    node_api: &CachedNodeApi,
+) -> Result<TopologyTables> {
     let name = opts.name();
     let namespace = opts.namespace().unwrap_or(cli.default_namespace());
     let selectors = opts.selectors();
     let jobs = resources::<Job>(name, namespace, selectors.as_deref(), cli.clone()).await?;
 
     if jobs.is_empty() {
-        bail!("No found job");
+        bail!(NotFound("job"));
     }
 
-    let labels_map = labels_set_by(&jobs)?;
+    let jobs = if opts.interactive {
+        pick_resources(jobs)?
+    } else {
+        jobs
+    };
+
+    let workloads = labels_set_by(&jobs)?;
     let topology_key = &opts.topology_key;
     let tables = topology_table_find_by(
-        labels_map,
+        workloads,
         namespace,
         topology_key,
+        opts.chunk_size,
         cli.clone(),
+        node_api,
         name.is_none(),
     )
     .await?;
@@ -31,7 +46,7 @@ pub async fn job(opts: ResourceWithNameOptions, cli: Client) -> Result<TopologyT
     Ok(tables)
 }
 
-pub fn labels_set_by(jobs: &[Job]) -> Result<BTreeMap<String, String>> {
+pub fn labels_set_by(jobs: &[Job]) -> Result<BTreeMap<String, Workload>> {
     let job_to_lables = |job: &Job| {
         let selector = &job
             .spec
@@ -50,7 +65,10 @@ pub fn labels_set_by(jobs: &[Job]) -> Result<BTreeMap<String, String>> {
         let kind = meta.kind.to_lowercase();
         let name = format!("{}/{}/{}", api_version, kind, job.name_any());
 
-        Ok((name, labels))
+        let template = job.spec.as_ref().map(|spec| &spec.template);
+        let workload = Workload::new(labels, template).with_labels(job.labels());
+
+        Ok((name, workload))
     };
 
     let labels = jobs
@@ -83,10 +101,9 @@ mod tests {
         let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
         let spawned = tokio::spawn(async move {
             pin_mut!(handle);
-            create_objects!(handle, "../tests/job_no_options_job.yaml", Job);
             create_objects!(handle, "../tests/nodes.yaml", Node);
-            create_objects!(handle, "../tests/job_no_options_pods1.yaml", Pod);
-            create_objects!(handle, "../tests/job_no_options_pods2.yaml", Pod);
+            create_objects!(handle, "../tests/job_no_options_job.yaml", Job);
+            create_objects!(handle, "../tests/job_no_options_pods.yaml", Pod);
 
             Ok(())
         });
@@ -98,7 +115,8 @@ mod tests {
             ..Default::default()
         };
 
-        let topology_tables = job(opts, cli).await?;
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = job(opts, cli, &node_api).await?;
 
         let mut topology_table_iter = topology_tables.into_iter();
 
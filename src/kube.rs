@@ -1,40 +1,83 @@
+use crate::{
+    arg::KubeConfigOptions,
+    profile::Profiler,
+    skew::{domains_of, node_names, only_node_running, only_pod_running, topology_values},
+    view,
+};
 use ::kube::{
-    api::{Api, ListParams},
-    config::KubeConfigOptions,
+    api::{Api, ListParams, ObjectList, ObjectMeta, PartialObjectMeta},
+    client::ClientBuilder,
+    config::{self, Cluster, Kubeconfig, NamedAuthInfo, NamedCluster, NamedContext},
     Client, Resource, ResourceExt,
 };
 use anyhow::*;
 use futures::future;
+use http::{header::WARNING, Response, Uri};
+use itertools::Itertools;
 use k8s_openapi::{
-    api::core::v1::{Node, NodeStatus, Pod, PodStatus},
+    api::core::v1::{
+        Namespace, Node, NodeSpec, NodeStatus, Pod, PodTemplateSpec, TopologySpreadConstraint,
+    },
     NamespaceResourceScope,
 };
+use regex::Regex;
 use serde::de::DeserializeOwned;
 use std::{collections::BTreeMap, fmt::Debug};
 use std::{
     collections::{HashMap, HashSet},
+    env,
+    ffi::OsStr,
     fmt::{Display, Formatter},
-    sync::RwLock,
+    future::Future,
+    io::{self, IsTerminal},
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
 };
+use tower::{limit::RateLimitLayer, util::MapResponseLayer};
 
+// Requirement of a label selector, e.g. app=web, tier!=db, app or !debug.
+// The value is empty for the existence requirements
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Label(pub String, pub String);
+pub struct Label(pub String, pub String, pub Operator);
 impl Display for Label {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        write!(f, "{}={}", &self.0, &self.1)
+        match self.2 {
+            Operator::Exists => write!(f, "{}", &self.0),
+            Operator::NotExists => write!(f, "!{}", &self.0),
+            _ => write!(f, "{}{}{}", &self.0, &self.2, &self.1),
+        }
     }
 }
 
 impl From<(&str, &str)> for Label {
     fn from(item: (&str, &str)) -> Self {
-        Label(item.0.to_owned(), item.1.to_owned())
+        Label(item.0.to_owned(), item.1.to_owned(), Operator::Equal)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Operator {
+    #[default]
+    Equal,
+    NotEqual,
+    Exists,
+    NotExists,
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Operator::Equal => write!(f, "="),
+            Operator::NotEqual => write!(f, "!="),
+            Operator::Exists => write!(f, ""),
+            Operator::NotExists => write!(f, "!"),
+        }
     }
 }
 
 pub trait LabelSelector {
     fn selector(&self) -> String;
-
-    fn labels(&self) -> BTreeMap<String, String>;
 }
 
 impl LabelSelector for Vec<Label> {
@@ -44,11 +87,159 @@ impl LabelSelector for Vec<Label> {
             .collect::<Vec<_>>()
             .join(",")
     }
+}
 
-    fn labels(&self) -> BTreeMap<String, String> {
-        self.iter()
-            .map(|label| (label.0.to_string(), label.1.to_string()))
-            .collect::<BTreeMap<_, _>>()
+// Pods selected by a workload and the spread constraints declared on its pod template
+#[derive(Debug, Clone, Default)]
+pub struct Workload {
+    pub selector: String,
+    pub spread_constraints: Vec<TopologySpreadConstraint>,
+
+    // Tolerance declared by the application team with annotations on the workload
+    pub max_skew_annotation: Option<u32>,
+    pub exempt: bool,
+
+    // Labels on the workload object itself, not the pods
+    pub labels: BTreeMap<String, String>,
+
+    // Desired replicas of a Deployment in a rollout, only with --exclude-surge
+    pub surge_limit: Option<u32>,
+}
+
+pub const MAX_SKEW_ANNOTATION: &str = "topology-skew.dev/max-skew";
+pub const EXEMPT_ANNOTATION: &str = "topology-skew.dev/exempt";
+
+impl Workload {
+    pub fn new(selector: String, template: Option<&PodTemplateSpec>) -> Self {
+        let spread_constraints = template
+            .and_then(|template| template.spec.as_ref())
+            .and_then(|spec| spec.topology_spread_constraints.clone())
+            .unwrap_or_default();
+
+        Self {
+            selector,
+            spread_constraints,
+            ..Default::default()
+        }
+    }
+
+    // Malformed values are ignored with a warning instead of failing the workload
+    pub fn with_annotations(self, annotations: &BTreeMap<String, String>) -> Self {
+        let max_skew_annotation =
+            annotations
+                .get(MAX_SKEW_ANNOTATION)
+                .and_then(|value| match value.parse::<u32>() {
+                    Result::Ok(max_skew) => Some(max_skew),
+                    Err(e) => {
+                        log::warn!("Ignore annotation {MAX_SKEW_ANNOTATION}={value}: {e}");
+                        None
+                    }
+                });
+        let exempt = annotations
+            .get(EXEMPT_ANNOTATION)
+            .is_some_and(|value| value == "true");
+
+        Self {
+            max_skew_annotation,
+            exempt,
+            ..self
+        }
+    }
+
+    pub fn with_labels(self, labels: &BTreeMap<String, String>) -> Self {
+        Self {
+            labels: labels.clone(),
+            ..self
+        }
+    }
+
+    // The newest pods are the surge of a rollout, the old ones keep running until replaced.
+    // Pods without a creation timestamp are treated as the newest
+    pub fn without_surge(&self, mut pods: Vec<Pod>) -> Vec<Pod> {
+        let Some(limit) = self.surge_limit else {
+            return pods;
+        };
+        pods.sort_by_key(|pod| {
+            let created = pod.creation_timestamp();
+            (created.is_none(), created)
+        });
+        pods.truncate(limit as usize);
+        pods
+    }
+
+    // The annotation takes precedence over maxSkew declared in topologySpreadConstraints
+    pub fn max_skew(&self, topology_key: &str) -> Option<u32> {
+        if self.max_skew_annotation.is_some() {
+            return self.max_skew_annotation;
+        }
+        self.spread_constraints
+            .iter()
+            .filter(|constraint| constraint.topology_key == topology_key)
+            .filter_map(|constraint| u32::try_from(constraint.max_skew).ok())
+            .min()
+    }
+
+    // The strictest minDomains among the constraints on the topology key
+    pub fn min_domains(&self, topology_key: &str) -> Option<u32> {
+        self.spread_constraints
+            .iter()
+            .filter(|constraint| constraint.topology_key == topology_key)
+            .filter_map(|constraint| constraint.min_domains)
+            .filter_map(|min_domains| u32::try_from(min_domains).ok())
+            .max()
+    }
+
+    // The constraint giving maxSkew is ScheduleAnyway, the annotation is always hard
+    pub fn is_soft(&self, topology_key: &str) -> bool {
+        if self.max_skew_annotation.is_some() {
+            return false;
+        }
+        self.spread_constraints
+            .iter()
+            .filter(|constraint| constraint.topology_key == topology_key)
+            .min_by_key(|constraint| constraint.max_skew)
+            .is_some_and(|constraint| constraint.when_unsatisfiable == "ScheduleAnyway")
+    }
+
+    // Topology keys of the declared constraints, `none` if nothing is declared
+    pub fn constraint_keys(&self) -> String {
+        if self.spread_constraints.is_empty() {
+            return String::from("none");
+        }
+
+        self.spread_constraints
+            .iter()
+            .map(|constraint| constraint.topology_key.as_str())
+            .unique()
+            .join(",")
+    }
+}
+
+// Keep only the fields used for the topology calculation,
+// because caching whole nodes with managedFields and images costs a lot of memory on large clusters
+fn slim_node(node: Node) -> Node {
+    let metadata = ObjectMeta {
+        name: node.metadata.name,
+        labels: node.metadata.labels,
+        creation_timestamp: node.metadata.creation_timestamp,
+        ..Default::default()
+    };
+    let spec = node.spec.map(|spec| NodeSpec {
+        unschedulable: spec.unschedulable,
+        taints: spec.taints,
+        ..Default::default()
+    });
+    let status = node.status.map(|status| NodeStatus {
+        conditions: status.conditions,
+        node_info: status.node_info,
+        allocatable: status.allocatable,
+        ..Default::default()
+    });
+
+    Node {
+        metadata,
+        spec,
+        status,
     }
 }
 
@@ -56,58 +247,209 @@ impl LabelSelector for Vec<Label> {
 pub struct CachedNodeApi {
     // Command line is short-lived and not reacquired
     cached: RwLock<HashMap<String, Node>>,
+
+    // Labels of all nodes for domain discovery, when only selected nodes are cached
+    domain_labels: Option<Vec<BTreeMap<String, String>>>,
+
+    // Without the list permission, nodes hosting the pods are fetched one by one,
+    // so that domains are derived only from them
+    degraded: Option<Api<Node>>,
+
+    // Nodes fetched one by one are checked against it, the API server cannot filter a get
+    node_selector: String,
+
+    // Handed to every scan along with the nodes
+    profiler: Profiler,
 }
 
 impl CachedNodeApi {
-    pub async fn try_from(cli: Client) -> Result<Self> {
+    pub async fn try_from(cli: Client, chunk_size: u32) -> Result<Self> {
+        Self::try_from_node_selector(cli, chunk_size, "").await
+    }
+
+    // Nodes not matching the selector are ignored entirely,
+    // both as domains and as the placement of pods
+    pub async fn try_from_node_selector(
+        cli: Client,
+        chunk_size: u32,
+        selector: &str,
+    ) -> Result<Self> {
         let api = Api::all(cli.clone());
-        let lp = ListParams::default();
-        let cached = api.list(&lp).await?;
+        let lp = ListParams::default().labels(selector);
+        let (cached, degraded) = match list_chunked(&api, &lp, chunk_size).await {
+            Result::Ok(cached) => (cached, None),
+            Err(e) if is_forbidden(&e) => {
+                log::warn!("Fetch nodes of pods one by one, because listing nodes is forbidden");
+                (Vec::new(), Some(api))
+            }
+            Err(e) => return Err(e),
+        };
 
         let cached = cached
             .into_iter()
-            .map(|node: Node| (node.name_any(), node))
+            .map(|node: Node| (node.name_any(), slim_node(node)))
             .collect::<HashMap<_, _>>();
 
         Ok(Self {
             cached: RwLock::new(cached),
+            domain_labels: None,
+            degraded,
+            profiler: Profiler::default(),
+            node_selector: selector.to_string(),
         })
     }
 
+    // Only nodes matching the selector are fetched by the API server,
+    // but domains are still discovered from the labels of all nodes
+    pub async fn try_from_selector(cli: Client, chunk_size: u32, selector: &str) -> Result<Self> {
+        let api = Api::all(cli.clone());
+        let lp = ListParams::default().labels(selector);
+        let cached = list_chunked(&api, &lp, chunk_size).await?;
+
+        let cached = cached
+            .into_iter()
+            .map(|node: Node| (node.name_any(), slim_node(node)))
+            .collect::<HashMap<_, _>>();
+
+        let lp = ListParams::default();
+        let domain_labels = list_metadata_chunked(&api, &lp, chunk_size)
+            .await?
+            .into_iter()
+            .map(|meta| meta.metadata.labels.unwrap_or_default())
+            .collect::<Vec<_>>();
+
+        Ok(Self {
+            cached: RwLock::new(cached),
+            domain_labels: Some(domain_labels),
+            degraded: None,
+            profiler: Profiler::default(),
+            node_selector: selector.to_string(),
+        })
+    }
+
+    // Nodes from the on-disk cache instead of the API server
+    pub fn from_nodes(nodes: Vec<Node>) -> Self {
+        let cached = nodes
+            .into_iter()
+            .map(|node| (node.name_any(), slim_node(node)))
+            .collect::<HashMap<_, _>>();
+
+        Self {
+            cached: RwLock::new(cached),
+            domain_labels: None,
+            degraded: None,
+            profiler: Profiler::default(),
+            node_selector: String::new(),
+        }
+    }
+
+    pub fn nodes(&self) -> Vec<Node> {
+        let cached = self.cached.read().unwrap();
+        cached
+            .values()
+            .sorted_by_key(|node| node.name_any())
+            .cloned()
+            .collect()
+    }
+
     // Domain is defined in the following documents
     //   https://kubernetes.io/docs/concepts/scheduling-eviction/topology-spread-constraints/#spread-constraint-definition
     // A domain is a particular instance of a topology
     pub fn domains(&self, topology_key: &str) -> HashSet<String> {
         let cached = self.cached.read().unwrap();
-        let has_topology = |(_, node): &(&String, &Node)| node.labels().get(topology_key).is_some();
-
-        let collect_domains = |mut domains: HashSet<String>, (_, node): (&String, &Node)| {
-            let topology = node.labels().get(topology_key).unwrap();
-            domains.insert(topology.to_owned());
-            domains
+        let labels_set = match &self.domain_labels {
+            Some(domain_labels) => domain_labels.iter().collect::<Vec<_>>(),
+            None => cached
+                .values()
+                .map(|node| node.labels())
+                .collect::<Vec<_>>(),
         };
 
-        cached
-            .iter()
-            .filter(has_topology)
-            .fold(HashSet::new(), collect_domains)
+        domains_of(labels_set, topology_key)
+    }
+
+    // When degraded, the cache grows with the nodes of every workload scanned so far,
+    // so only the nodes of the workload itself are used to keep its skew independent of the order
+    pub fn domains_of_nodes(&self, topology_key: &str, nodes: &[Node]) -> HashSet<String> {
+        match self.is_degraded() {
+            true => domains_of(nodes.iter().map(|node| node.labels()), topology_key),
+            false => self.domains(topology_key),
+        }
+    }
+
+    // Domains where no node accepts new pods, with the reason.
+    // Domains are known only through their nodes, so a domain without any node is never reported
+    pub fn ineligible_domains(&self, topology_key: &str) -> BTreeMap<String, &'static str> {
+        let cached = self.cached.read().unwrap();
+        let mut nodes_by_domain: BTreeMap<String, Vec<&Node>> = BTreeMap::new();
+        for node in cached.values() {
+            if let Some(domain) = node.labels().get(topology_key) {
+                nodes_by_domain
+                    .entry(domain.clone())
+                    .or_default()
+                    .push(node);
+            }
+        }
+
+        nodes_by_domain
+            .into_iter()
+            .filter_map(|(domain, nodes)| {
+                let reason = if nodes.iter().all(|node| is_cordoned(node)) {
+                    "cordoned"
+                } else if nodes
+                    .iter()
+                    .all(|node| is_cordoned(node) || is_tainted(node))
+                {
+                    "tainted"
+                } else {
+                    return None;
+                };
+                Some((domain, reason))
+            })
+            .collect()
+    }
+
+    pub fn with_profiler(self, profiler: Profiler) -> Self {
+        Self { profiler, ..self }
+    }
+
+    pub fn profiler(&self) -> &Profiler {
+        &self.profiler
+    }
+
+    // Domains are derived only from the nodes hosting the pods
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.is_some()
     }
 
     // Command line is short-lived and not reacquired
     pub async fn get(&self, node_name: &str) -> Option<Node> {
-        self.cached.read().unwrap().get(node_name).cloned()
+        if let Some(node) = self.cached.read().unwrap().get(node_name) {
+            return Some(node.clone());
+        }
+
+        let api = self.degraded.as_ref()?;
+        let node = match with_retry(|| api.get_opt(node_name)).await {
+            Result::Ok(node) => slim_node(node?),
+            Err(e) => {
+                log::warn!("Skip pods on node {node_name}: {e}");
+                return None;
+            }
+        };
+        if !match_selector(&self.node_selector, node.labels()) {
+            log::debug!("Skip pods on node {node_name} not matching the node selector");
+            return None;
+        }
+        self.cached
+            .write()
+            .unwrap()
+            .insert(node_name.to_string(), node.clone());
+        Some(node)
     }
 
-    pub async fn list(&self, labels: &BTreeMap<String, String>) -> Vec<Node> {
+    pub async fn list(&self, selector: &str) -> Vec<Node> {
         let find_by_label = |(_, node): (&String, &Node)| {
-            labels
-                .iter()
-                .all(|search_label| {
-                    node.labels()
-                        .iter()
-                        .any(|node_label| search_label == node_label)
-                })
-                .then_some(node.clone())
+            match_selector(selector, node.labels()).then_some(node.clone())
         };
 
         let nodes = self
@@ -122,19 +464,401 @@ impl CachedNodeApi {
     }
 }
 
-pub async fn kube_client(
-    context: Option<String>,
-    cluster: Option<String>,
-    user: Option<String>,
-) -> Result<Client> {
-    let config = kube::Config::from_kubeconfig(&KubeConfigOptions {
-        context,
-        cluster,
-        user,
+pub fn is_cordoned(node: &Node) -> bool {
+    node.spec
+        .as_ref()
+        .and_then(|spec| spec.unschedulable)
+        .unwrap_or(false)
+}
+
+// Cordon adds the unschedulable taint, which is not counted as tainted
+pub fn is_tainted(node: &Node) -> bool {
+    let taints = node.spec.as_ref().and_then(|spec| spec.taints.as_ref());
+    taints.is_some_and(|taints| {
+        taints.iter().any(|taint| {
+            taint.effect == "NoSchedule" && taint.key != "node.kubernetes.io/unschedulable"
+        })
     })
-    .await?;
+}
 
-    Ok(Client::try_from(config)?)
+// Used for the entries created only from the connection flags
+const DIRECT_CONNECTION_NAME: &str = "kubectl-topology-skew";
+
+fn load_kubeconfig(opts: &KubeConfigOptions) -> Result<Kubeconfig> {
+    let kubeconfig = match &opts.kubeconfig {
+        Some(paths) => read_kubeconfig(paths),
+        None => Kubeconfig::read().map_err(Error::from),
+    };
+
+    // A kubeconfig is not required if the API server is given directly
+    match kubeconfig {
+        Result::Ok(kubeconfig) => Ok(kubeconfig),
+        Err(_) if opts.server.is_some() => Ok(Kubeconfig::default()),
+        Err(e) => Err(e),
+    }
+}
+
+// Context names matching --contexts, in the order of the patterns
+pub fn context_names(opts: &KubeConfigOptions) -> Result<Vec<String>> {
+    let kubeconfig = load_kubeconfig(opts)?;
+    let names = kubeconfig
+        .contexts
+        .iter()
+        .map(|context| context.name.clone())
+        .collect::<Vec<_>>();
+
+    let mut matched = Vec::new();
+    for pattern in &opts.contexts {
+        let regex = glob_regex(pattern)?;
+        let found = names
+            .iter()
+            .filter(|name| regex.is_match(name))
+            .collect::<Vec<_>>();
+
+        if found.is_empty() {
+            bail!("No found context: {pattern}");
+        }
+        matched.extend(found.into_iter().cloned());
+    }
+
+    Ok(matched.into_iter().unique().collect())
+}
+
+fn glob_regex(pattern: &str) -> Result<Regex> {
+    let regex = regex::escape(pattern)
+        .replace(r"\*", ".*")
+        .replace(r"\?", ".");
+    Regex::new(&format!("^{regex}$")).with_context(|| format!("Fail to parse pattern: {pattern}"))
+}
+
+async fn kube_config(opts: &KubeConfigOptions) -> Result<kube::Config> {
+    let mut kubeconfig = load_kubeconfig(opts)?;
+
+    if opts.has_connection_flags() {
+        override_kubeconfig(&mut kubeconfig, opts);
+    }
+
+    let options = config::KubeConfigOptions {
+        context: opts.context.clone(),
+        cluster: opts.cluster.clone(),
+        user: opts.user.clone(),
+    };
+    let mut config = kube::Config::from_custom_kubeconfig(kubeconfig, &options).await?;
+
+    match &opts.proxy_url {
+        Some(proxy_url) => config.proxy_url = Some(proxy_url.parse()?),
+        None if config.proxy_url.is_none() => {
+            config.proxy_url = proxy_from_env(&config.cluster_url)
+        }
+        None => {}
+    }
+
+    // Zero disables the default timeouts of kube as well, like kubectl.
+    // Without the flag, the defaults of kube are kept
+    if let Some(request_timeout) = opts.request_timeout {
+        let timeout = (!request_timeout.is_zero()).then_some(request_timeout);
+        config.connect_timeout = timeout;
+        config.read_timeout = timeout;
+        config.write_timeout = timeout;
+    }
+
+    Ok(config)
+}
+
+// URL of the API server, which tells clusters apart regardless of the context name
+pub async fn cluster_url(opts: &KubeConfigOptions) -> Result<String> {
+    let config = kube_config(opts).await?;
+    Ok(config.cluster_url.to_string())
+}
+
+pub async fn kube_client(opts: KubeConfigOptions) -> Result<Client> {
+    let config = kube_config(&opts).await?;
+    // Shared by clones of the layer, so that each warning is printed once per client
+    let printed = Arc::new(Mutex::new(HashSet::new()));
+    let builder = ClientBuilder::try_from(config)?.with_layer(&MapResponseLayer::new(move |res| {
+        print_warnings(res, &printed, &mut io::stderr())
+    }));
+    let cli = match opts.qps.filter(|qps| *qps > 0.0) {
+        // Up to `burst` requests are allowed in the period that matches the qps on average
+        Some(qps) => {
+            let period = Duration::from_secs_f64(opts.burst as f64 / qps);
+            builder
+                .with_layer(&RateLimitLayer::new(opts.burst, period))
+                .build()
+        }
+        None => builder.build(),
+    };
+
+    Ok(cli)
+}
+
+// Same as kubectl, e.g. deprecated APIs and throttling by API priority and fairness.
+// Printed whatever the log level, but only the first time
+fn print_warnings<B>(
+    res: Response<B>,
+    printed: &Mutex<HashSet<String>>,
+    out: &mut impl io::Write,
+) -> Response<B> {
+    for value in res.headers().get_all(WARNING) {
+        let Some(text) = value.to_str().ok().and_then(warning_text) else {
+            continue;
+        };
+        let mut printed = printed.lock().unwrap_or_else(|e| e.into_inner());
+        if !printed.contains(&text) {
+            let _ = writeln!(out, "Warning: {text}");
+            printed.insert(text);
+        }
+    }
+    res
+}
+
+// Text of a warning header formatted as `299 - "text"`
+fn warning_text(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, ' ');
+    let (_code, _agent) = (parts.next()?, parts.next()?);
+    let text = parts.next()?.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some(text.replace(r#"\""#, "\""))
+}
+
+// Same as kubectl, $NO_PROXY takes precedence over $HTTPS_PROXY
+fn proxy_from_env(cluster_url: &Uri) -> Option<Uri> {
+    let var = |names: [&str; 2]| {
+        names
+            .iter()
+            .find_map(|name| env::var(name).ok())
+            .filter(|value| !value.is_empty())
+    };
+
+    let host = cluster_url.host()?;
+    let no_proxy = var(["NO_PROXY", "no_proxy"]).unwrap_or_default();
+    if is_no_proxy(host, &no_proxy) {
+        return None;
+    }
+
+    let proxy_url = match cluster_url.scheme_str() {
+        Some("http") => var(["HTTP_PROXY", "http_proxy"]),
+        _ => var(["HTTPS_PROXY", "https_proxy"]),
+    }?;
+
+    proxy_url.parse().ok()
+}
+
+fn is_no_proxy(host: &str, no_proxy: &str) -> bool {
+    let matches = |entry: &str| {
+        let domain = entry.trim_start_matches('.');
+        entry == "*" || host == domain || host.ends_with(&format!(".{domain}"))
+    };
+
+    no_proxy
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .any(matches)
+}
+
+// Connection flags take precedence over the kubeconfig like kubectl
+fn override_kubeconfig(kubeconfig: &mut Kubeconfig, opts: &KubeConfigOptions) {
+    let context_name = opts
+        .context
+        .clone()
+        .or_else(|| kubeconfig.current_context.clone())
+        .unwrap_or_else(|| DIRECT_CONNECTION_NAME.to_string());
+
+    if !kubeconfig.contexts.iter().any(|c| c.name == context_name) {
+        kubeconfig.contexts.push(NamedContext {
+            name: context_name.clone(),
+            context: None,
+        });
+    }
+    let context = kubeconfig
+        .contexts
+        .iter_mut()
+        .find(|c| c.name == context_name)
+        .map(|c| {
+            let default_context = || config::Context {
+                cluster: DIRECT_CONNECTION_NAME.to_string(),
+                user: DIRECT_CONNECTION_NAME.to_string(),
+                ..Default::default()
+            };
+            c.context.get_or_insert_with(default_context).clone()
+        })
+        .unwrap_or_default();
+
+    kubeconfig.current_context = Some(context_name);
+
+    let cluster_name = opts.cluster.clone().unwrap_or(context.cluster);
+    if !kubeconfig.clusters.iter().any(|c| c.name == cluster_name) {
+        kubeconfig.clusters.push(NamedCluster {
+            name: cluster_name.clone(),
+            cluster: None,
+        });
+    }
+    if let Some(cluster) = kubeconfig
+        .clusters
+        .iter_mut()
+        .find(|c| c.name == cluster_name)
+        .map(|c| c.cluster.get_or_insert_with(Cluster::default))
+    {
+        if let Some(server) = &opts.server {
+            cluster.server = Some(server.clone());
+        }
+        if let Some(path) = &opts.certificate_authority {
+            cluster.certificate_authority = Some(path.clone());
+            cluster.certificate_authority_data = None;
+        }
+        if opts.insecure_skip_tls_verify {
+            cluster.insecure_skip_tls_verify = Some(true);
+        }
+    }
+
+    let user_name = opts.user.clone().unwrap_or(context.user);
+    if !kubeconfig.auth_infos.iter().any(|a| a.name == user_name) {
+        kubeconfig.auth_infos.push(NamedAuthInfo {
+            name: user_name.clone(),
+            auth_info: None,
+        });
+    }
+    if let Some(auth_info) = kubeconfig
+        .auth_infos
+        .iter_mut()
+        .find(|a| a.name == user_name)
+        .map(|a| a.auth_info.get_or_insert_with(Default::default))
+    {
+        if let Some(token) = &opts.token {
+            auth_info.token = Some(token.clone().into());
+            auth_info.token_file = None;
+        }
+    }
+}
+
+// Same rules as $KUBECONFIG, the first file to set a value wins
+fn read_kubeconfig(paths: &OsStr) -> Result<Kubeconfig> {
+    let merge = |merged: Kubeconfig, path: PathBuf| {
+        let kubeconfig = Kubeconfig::read_from(&path)
+            .with_context(|| format!("Fail to read kubeconfig: {}", path.display()))?;
+        Ok(merged.merge(kubeconfig)?)
+    };
+
+    env::split_paths(paths)
+        .filter(|path| !path.as_os_str().is_empty())
+        .try_fold(Kubeconfig::default(), merge)
+}
+
+const RETRY_MAX_ATTEMPTS: u32 = 5;
+const RETRY_INITIAL_INTERVAL: Duration = Duration::from_millis(200);
+const RETRY_MAX_INTERVAL: Duration = Duration::from_secs(5);
+
+fn is_transient(e: &kube::Error) -> bool {
+    match e {
+        kube::Error::Api(res) => res.code == 429 || res.code >= 500,
+        kube::Error::HyperError(_) | kube::Error::Service(_) => true,
+        _ => false,
+    }
+}
+
+// No objects to evaluate, which is not a failure with --ignore-not-found
+#[derive(Debug)]
+pub struct NotFound(pub &'static str);
+
+impl std::fmt::Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "No found {}", self.0)
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+pub fn is_not_found(e: &Error) -> bool {
+    is_missing_resource(e) || e.is::<NotFound>()
+}
+
+// A named resource does not exist, unlike a selection without objects
+pub fn is_missing_resource(e: &Error) -> bool {
+    matches!(e.downcast_ref::<kube::Error>(), Some(kube::Error::Api(res)) if res.code == 404)
+}
+
+// Failure to talk to the API server, including authentication and kubeconfig errors
+pub fn is_api_error(e: &Error) -> bool {
+    let is_api_error = |cause: &(dyn std::error::Error + 'static)| {
+        cause.is::<kube::Error>()
+            || cause.is::<config::KubeconfigError>()
+            || cause.is::<config::InferConfigError>()
+    };
+    e.chain().any(is_api_error)
+}
+
+// Lack of RBAC permission, e.g. no list verb on the resource
+pub fn is_forbidden(e: &Error) -> bool {
+    matches!(e.downcast_ref::<kube::Error>(), Some(kube::Error::Api(res)) if res.code == 403)
+}
+
+// Retry transient errors with exponential backoff,
+// so that a single flaky request doesn't abort a long scan
+pub async fn with_retry<T, F, Fut>(mut request: F) -> kube::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = kube::Result<T>>,
+{
+    let mut interval = RETRY_INITIAL_INTERVAL;
+    let mut attempt = 1;
+
+    loop {
+        match request().await {
+            Err(e) if attempt < RETRY_MAX_ATTEMPTS && is_transient(&e) => {
+                log::warn!("Retry the request in {interval:?}: {e}");
+                tokio::time::sleep(interval).await;
+                interval = (interval * 2).min(RETRY_MAX_INTERVAL);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Paginated list like kubectl --chunk-size, 0 lists everything at once
+pub async fn list_chunked<K>(api: &Api<K>, params: &ListParams, chunk_size: u32) -> Result<Vec<K>>
+where
+    K: Clone + DeserializeOwned + Debug,
+{
+    paginate(params, chunk_size, |params| async move {
+        api.list(&params).await
+    })
+    .await
+}
+
+pub async fn list_metadata_chunked<K>(
+    api: &Api<K>,
+    params: &ListParams,
+    chunk_size: u32,
+) -> Result<Vec<PartialObjectMeta<K>>>
+where
+    K: Clone + DeserializeOwned + Debug,
+{
+    let list = |params: ListParams| async move { api.list_metadata(&params).await };
+    paginate(params, chunk_size, list).await
+}
+
+async fn paginate<T, F, Fut>(params: &ListParams, chunk_size: u32, list: F) -> Result<Vec<T>>
+where
+    T: Clone,
+    F: Fn(ListParams) -> Fut,
+    Fut: Future<Output = kube::Result<ObjectList<T>>>,
+{
+    let mut params = params.clone();
+    if chunk_size > 0 {
+        params = params.limit(chunk_size);
+    }
+
+    let mut items = Vec::new();
+    loop {
+        let list = with_retry(|| list(params.clone())).await?;
+        items.extend(list.items);
+
+        match list.metadata.continue_.filter(|token| !token.is_empty()) {
+            Some(token) => params = params.continue_token(&token),
+            None => return Ok(items),
+        }
+    }
 }
 
 pub async fn resources<K>(
@@ -152,53 +876,60 @@ where
 
     let resources = match (name, label) {
         (Some(n), None) => {
-            let resource = api.get(n).await?;
+            let resource = with_retry(|| api.get(n)).await?;
             vec![resource]
         }
         (None, Some(l)) => {
             let params = ListParams::default().labels(l);
-            api.list(&params).await?.into_iter().collect::<Vec<_>>()
+            with_retry(|| api.list(&params))
+                .await?
+                .into_iter()
+                .collect::<Vec<_>>()
         }
         _ => {
             let params = ListParams::default();
-            api.list(&params).await?.into_iter().collect::<Vec<_>>()
+            with_retry(|| api.list(&params))
+                .await?
+                .into_iter()
+                .collect::<Vec<_>>()
         }
     };
 
     Ok(resources)
 }
 
-pub fn only_pod_running(pods: Vec<Pod>) -> Vec<Pod> {
-    let is_running = |status: &PodStatus| status.phase.as_ref().map(|phase| phase == "Running");
-    let only_running = |pod: &Pod| pod.status.as_ref().and_then(is_running).unwrap_or(false);
+// Narrows listed resources down to the one picked on the terminal
+pub fn pick_resources<K: ResourceExt>(resources: Vec<K>) -> Result<Vec<K>> {
+    if !io::stdin().is_terminal() {
+        bail!("Interactive selection requires a terminal on stdin");
+    }
+    let names = resources
+        .iter()
+        .map(ResourceExt::name_any)
+        .collect::<Vec<_>>();
+    let index = view::pick(&names, io::stdin().lock(), io::stderr())?;
 
-    pods.into_iter().filter(only_running).collect::<Vec<_>>()
+    Ok(resources.into_iter().nth(index).into_iter().collect())
 }
 
-pub fn only_node_running(nodes: Vec<Node>) -> Vec<Node> {
-    let is_ready = |status: &NodeStatus| {
-        status.conditions.as_ref().map(|conditions| {
-            conditions
-                .iter()
-                .any(|condi| condi.type_ == "Ready" && condi.status == "True")
-        })
-    };
-    let only_running = |node: &Node| node.status.as_ref().and_then(is_ready).is_some();
-    nodes.into_iter().filter(only_running).collect::<Vec<_>>()
-}
+pub async fn namespaces(cli: Client) -> Result<Vec<String>> {
+    let namespaces = namespace_labels(cli).await?.into_keys().collect();
 
-pub fn topology_values(topology_key: &str, nodes: &[Node]) -> Vec<String> {
-    let find_topology_value = |node: &Node| node.labels().get(topology_key).map(String::from);
-    nodes
-        .iter()
-        .filter_map(find_topology_value)
-        .collect::<Vec<_>>()
+    Ok(namespaces)
 }
 
-pub fn node_names(pods: &[Pod]) -> Vec<&str> {
-    pods.iter()
-        .filter_map(|pod| pod.spec.as_ref().and_then(|spec| spec.node_name.as_deref()))
-        .collect::<Vec<_>>()
+// Labels of each namespace, keyed by namespace name
+pub async fn namespace_labels(cli: Client) -> Result<BTreeMap<String, BTreeMap<String, String>>> {
+    let api: Api<Namespace> = Api::all(cli);
+    let params = ListParams::default();
+    let namespaces = with_retry(|| api.list(&params))
+        .await
+        .context("Fail to get namespaces")?
+        .into_iter()
+        .map(|namespace| (namespace.name_any(), namespace.labels().clone()))
+        .collect();
+
+    Ok(namespaces)
 }
 
 pub async fn nodes_by(pods: &[Pod], api: &CachedNodeApi) -> Result<Vec<Node>> {
@@ -220,7 +951,33 @@ pub async fn nodes_by(pods: &[Pod], api: &CachedNodeApi) -> Result<Vec<Node>> {
     Ok(nodes)
 }
 
-pub async fn pods_by(labels_set: &[&str], namespace: &str, cli: Client) -> Result<Vec<Pod>> {
+pub async fn pods_by(
+    labels_set: &[&str],
+    namespace: &str,
+    chunk_size: u32,
+    cli: Client,
+) -> Result<Vec<Pod>> {
+    let pods = pods_in_any_phase(labels_set, namespace, chunk_size, cli).await?;
+
+    Ok(only_pod_running(pods))
+}
+
+// Pods holding a slot on a node in all namespaces, succeeded and failed pods release theirs
+pub async fn scheduled_pods(cli: Client, chunk_size: u32) -> Result<Vec<Pod>> {
+    let api: Api<Pod> = Api::all(cli);
+    let params = ListParams::default().fields("status.phase!=Succeeded,status.phase!=Failed");
+
+    list_chunked(&api, &params, chunk_size)
+        .await
+        .context("Fail to get pods")
+}
+
+pub async fn pods_in_any_phase(
+    labels_set: &[&str],
+    namespace: &str,
+    chunk_size: u32,
+    cli: Client,
+) -> Result<Vec<Pod>> {
     let api: Api<Pod> = Api::namespaced(cli, namespace);
 
     let get_pods = |labels: &&str| {
@@ -228,10 +985,9 @@ pub async fn pods_by(labels_set: &[&str], namespace: &str, cli: Client) -> Resul
 
         let api = &api;
         Ok(async move {
-            api.list(&params)
+            list_chunked(api, &params, chunk_size)
                 .await
                 .context("Fail to get pods")
-                .map(|objs| objs.into_iter().collect::<Vec<_>>())
         })
     };
 
@@ -248,11 +1004,67 @@ pub async fn pods_by(labels_set: &[&str], namespace: &str, cli: Client) -> Resul
         .flatten()
         .collect::<Vec<_>>();
 
-    let pods = only_pod_running(pods);
-
     Ok(pods)
 }
 
+// List pods once per namespace and attribute them to workloads in memory,
+// instead of one list call per workload. A single workload is still selected by the API server
+pub async fn pods_by_workloads(
+    workloads: &BTreeMap<String, Workload>,
+    namespace: &str,
+    chunk_size: u32,
+    cli: Client,
+) -> Result<BTreeMap<String, Vec<Pod>>> {
+    if workloads.len() == 1 {
+        let mut pods_map = BTreeMap::new();
+        for (name, workload) in workloads {
+            let selector = [workload.selector.as_str()];
+            let pods = pods_by(&selector, namespace, chunk_size, cli.clone());
+            let pods = pods.await?;
+            pods_map.insert(name.clone(), pods);
+        }
+        return Ok(pods_map);
+    }
+
+    let pods = pods_by(&[""], namespace, chunk_size, cli).await?;
+
+    let select_pods = |(name, workload): (&String, &Workload)| {
+        let selected = pods
+            .iter()
+            .filter(|pod| match_selector(&workload.selector, pod.labels()))
+            .cloned()
+            .collect::<Vec<_>>();
+        (name.clone(), selected)
+    };
+
+    Ok(workloads.iter().map(select_pods).collect())
+}
+
+// Match labels with a selector string such as `app=web,tier!=db,env,!debug`
+pub fn match_selector(selector: &str, labels: &BTreeMap<String, String>) -> bool {
+    let matches = |requirement: &str| {
+        if let Some((key, value)) = requirement.split_once("!=") {
+            return labels.get(key.trim()) != Some(&value.trim().to_string());
+        }
+        if let Some((key, value)) = requirement
+            .split_once("==")
+            .or_else(|| requirement.split_once('='))
+        {
+            return labels.get(key.trim()) == Some(&value.trim().to_string());
+        }
+        match requirement.strip_prefix('!') {
+            Some(key) => !labels.contains_key(key.trim()),
+            None => labels.contains_key(requirement),
+        }
+    };
+
+    selector
+        .split(',')
+        .map(str::trim)
+        .filter(|requirement| !requirement.is_empty())
+        .all(matches)
+}
+
 // Retrieve scheduled topology values and domain information to verify spreading status
 pub async fn spreading_status(
     nodes: &[Node],
@@ -260,21 +1072,28 @@ pub async fn spreading_status(
     api: &CachedNodeApi,
 ) -> Result<(Vec<String>, HashSet<String>)> {
     let topology_values = topology_values(topology_key, nodes);
-    let domains = api.domains(topology_key);
+    let domains = api.domains_of_nodes(topology_key, nodes);
     Ok((topology_values, domains))
 }
 
 #[cfg(test)]
 pub mod tests {
 
+    // Objects of a yaml file, empty documents are null and skipped
+    macro_rules! load_objects {
+        ($file:expr, $return_type:ty) => {
+            serde_yaml::Deserializer::from_str(include_str!($file))
+                .flat_map(Option::<$return_type>::deserialize)
+                .flatten()
+                .collect::<Vec<$return_type>>()
+        };
+    }
+
     // ref test_mock https://github.com/kube-rs/kube/blob/main/kube-client/src/client/mod.rs
     macro_rules! create_objects {
         ($handle:expr, $file:expr, $return_type:ty) => {
             let (_, send) = $handle.next_request().await.unwrap();
-            let yaml = include_str!($file);
-            let items = serde_yaml::Deserializer::from_str(yaml)
-                .flat_map(<$return_type>::deserialize)
-                .collect::<Vec<_>>();
+            let items = $crate::kube::tests::load_objects!($file, $return_type);
             let types: TypeMeta = TypeMeta::list::<$return_type>();
             let metadata: ListMeta = Default::default();
 
@@ -287,5 +1106,394 @@ pub mod tests {
         };
     }
 
+    // Failed request such as 403 of RBAC and 404 of a missing object
+    macro_rules! status_response {
+        ($code:expr) => {{
+            let code: u16 = $code;
+            let reason = match code {
+                403 => "Forbidden",
+                404 => "NotFound",
+                _ => "InternalError",
+            };
+            let status = serde_json::json!({
+                "kind": "Status",
+                "apiVersion": "v1",
+                "status": "Failure",
+                "message": reason,
+                "reason": reason,
+                "code": code,
+            });
+            Response::builder()
+                .status(code)
+                .body(Body::from(serde_json::to_vec(&status)?))?
+        }};
+    }
+
+    macro_rules! send_status {
+        ($handle:expr, $code:expr) => {
+            let (_, send) = $handle.next_request().await.unwrap();
+            send.send_response($crate::kube::tests::status_response!($code));
+        };
+    }
+
     pub(crate) use create_objects;
+    pub(crate) use load_objects;
+    pub(crate) use send_status;
+    pub(crate) use status_response;
+
+    use super::*;
+    use clap::Parser;
+    use serde::Deserialize;
+
+    #[test]
+    fn read_kubeconfig_merge() -> Result<()> {
+        let dir = env::temp_dir();
+        let first = dir.join("kubectl-topology-skew-kubeconfig-first.yaml");
+        let second = dir.join("kubectl-topology-skew-kubeconfig-second.yaml");
+        std::fs::write(
+            &first,
+            "current-context: first\ncontexts:\n- name: first\n  context:\n    cluster: first\n    user: first\n",
+        )?;
+        std::fs::write(
+            &second,
+            "current-context: second\ncontexts:\n- name: second\n  context:\n    cluster: second\n    user: second\n",
+        )?;
+
+        let paths = env::join_paths([&first, &second])?;
+        let kubeconfig = read_kubeconfig(&paths)?;
+        assert_eq!(kubeconfig.current_context.as_deref(), Some("first"));
+        assert_eq!(kubeconfig.contexts.len(), 2);
+
+        let missing = dir.join("kubectl-topology-skew-kubeconfig-missing.yaml");
+        assert!(read_kubeconfig(missing.as_os_str()).is_err());
+
+        std::fs::remove_file(&first)?;
+        std::fs::remove_file(&second)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn context_names_glob() -> Result<()> {
+        let path = env::temp_dir().join("kubectl-topology-skew-kubeconfig-contexts.yaml");
+        let contexts = ["prod-us", "prod-eu", "staging"]
+            .iter()
+            .map(|name| {
+                format!("- name: {name}\n  context:\n    cluster: {name}\n    user: {name}\n")
+            })
+            .join("");
+        std::fs::write(&path, format!("contexts:\n{contexts}"))?;
+
+        let context_names = |contexts: &str| {
+            let kubeconfig = path.as_os_str().to_str().unwrap();
+            let args = [
+                "kubectl-topology_skew",
+                "--kubeconfig",
+                kubeconfig,
+                "--contexts",
+                contexts,
+            ];
+            context_names(&KubeConfigOptions::parse_from(args))
+        };
+
+        assert_eq!(context_names("prod-*")?, vec!["prod-us", "prod-eu"]);
+        assert_eq!(
+            context_names("staging,prod-?u,staging")?,
+            vec!["staging", "prod-eu"]
+        );
+        assert!(context_names("dev").is_err());
+
+        std::fs::remove_file(&path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn override_kubeconfig_direct_connection() -> Result<()> {
+        let opts = KubeConfigOptions::parse_from([
+            "kubectl-topology_skew",
+            "--server",
+            "https://192.0.2.1:6443",
+            "--token",
+            "secret",
+            "--insecure-skip-tls-verify",
+        ]);
+
+        let mut kubeconfig = Kubeconfig::default();
+        override_kubeconfig(&mut kubeconfig, &opts);
+
+        let options = config::KubeConfigOptions::default();
+        let config = kube::Config::from_custom_kubeconfig(kubeconfig, &options).await?;
+        assert_eq!(config.cluster_url.to_string(), "https://192.0.2.1:6443/");
+        assert!(config.accept_invalid_certs);
+        assert!(config.auth_info.token.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn kube_config_request_timeout() -> Result<()> {
+        let args = |extra: &[&str]| {
+            let base = [
+                "kubectl-topology_skew",
+                "--kubeconfig",
+                "/nonexistent",
+                "--server",
+                "https://192.0.2.1:6443",
+            ];
+            KubeConfigOptions::parse_from([&base[..], extra].concat())
+        };
+        let defaults = kube::Config::new("https://192.0.2.1:6443".parse()?);
+
+        // Without the flag, the timeouts of kube are kept
+        let config = kube_config(&args(&[])).await?;
+        assert_eq!(config.connect_timeout, defaults.connect_timeout);
+        assert_eq!(config.read_timeout, defaults.read_timeout);
+        assert!(config.read_timeout.is_some());
+
+        let config = kube_config(&args(&["--request-timeout", "0"])).await?;
+        assert_eq!(config.connect_timeout, None);
+        assert_eq!(config.read_timeout, None);
+
+        let config = kube_config(&args(&["--request-timeout", "30s"])).await?;
+        assert_eq!(config.read_timeout, Some(Duration::from_secs(30)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn is_no_proxy_match() {
+        let no_proxy = "localhost, .svc,example.com";
+
+        assert!(is_no_proxy("localhost", no_proxy));
+        assert!(is_no_proxy("kubernetes.default.svc", no_proxy));
+        assert!(is_no_proxy("example.com", no_proxy));
+        assert!(is_no_proxy("api.example.com", no_proxy));
+        assert!(!is_no_proxy("example.org", no_proxy));
+        assert!(!is_no_proxy("badexample.com", no_proxy));
+        assert!(is_no_proxy("example.org", "*"));
+        assert!(!is_no_proxy("example.org", ""));
+    }
+
+    #[test]
+    fn warning_text_of_header() {
+        assert_eq!(
+            warning_text(r#"299 - "batch/v1beta1 CronJob is deprecated""#).as_deref(),
+            Some("batch/v1beta1 CronJob is deprecated")
+        );
+        assert_eq!(
+            warning_text(r#"299 - "unknown field \"spec.foo\"""#).as_deref(),
+            Some(r#"unknown field "spec.foo""#)
+        );
+        assert_eq!(warning_text("299 -"), None);
+        assert_eq!(warning_text("299 - unquoted"), None);
+    }
+
+    #[test]
+    fn print_warnings_once() {
+        let response = || {
+            Response::builder()
+                .header(WARNING, r#"299 - "batch/v1beta1 CronJob is deprecated""#)
+                .header(WARNING, r#"299 - "unknown field \"spec.foo\"""#)
+                .body(())
+                .unwrap()
+        };
+        let printed = Mutex::new(HashSet::new());
+        let mut out = Vec::new();
+
+        // Without a logger, as with the default verbosity
+        print_warnings(response(), &printed, &mut out);
+        print_warnings(response(), &printed, &mut out);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Warning: batch/v1beta1 CronJob is deprecated\n\
+             Warning: unknown field \"spec.foo\"\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn with_retry_transient() -> Result<()> {
+        let error = |code: u16| {
+            kube::Error::Api(kube::core::ErrorResponse {
+                status: String::from("Failure"),
+                message: String::new(),
+                reason: String::new(),
+                code,
+            })
+        };
+
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let result = with_retry(|| async {
+            match attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 => Err(error(503)),
+                1 => Err(error(429)),
+                _ => kube::Result::Ok("ok"),
+            }
+        })
+        .await?;
+        assert_eq!(result, "ok");
+        assert_eq!(attempts.into_inner(), 3);
+
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let result = with_retry(|| async {
+            attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            kube::Result::<()>::Err(error(403))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.into_inner(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn list_chunked_continue() -> Result<()> {
+        use futures::pin_mut;
+        use http::{Request, Response};
+        use kube::api::{ListMeta, ObjectList, TypeMeta};
+        use kube::client::Body;
+        use tower_test::mock;
+
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            for (continue_, name) in [(Some("token"), "node1"), (None, "node2")] {
+                let (request, send) = handle.next_request().await.unwrap();
+                let query = request.uri().query().unwrap_or_default().to_string();
+                assert!(query.contains("limit=1"));
+                assert_eq!(continue_.is_none(), query.contains("continue=token"));
+
+                let list = ObjectList {
+                    types: TypeMeta::list::<Node>(),
+                    metadata: ListMeta {
+                        continue_: continue_.map(String::from),
+                        ..Default::default()
+                    },
+                    items: vec![Node {
+                        metadata: kube::api::ObjectMeta {
+                            name: Some(name.to_string()),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    }],
+                };
+                send.send_response(
+                    Response::builder().body(Body::from(serde_json::to_vec(&list)?))?,
+                );
+            }
+            Ok(())
+        });
+
+        let api: Api<Node> = Api::all(Client::new(mock_service, "default"));
+        let nodes = list_chunked(&api, &ListParams::default(), 1).await?;
+        spawned.await??;
+
+        let names = nodes.iter().map(ResourceExt::name_any).collect::<Vec<_>>();
+        assert_eq!(names, vec!["node1", "node2"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn without_surge_keep_oldest() {
+        let pod = |name: &str, created: Option<&str>| {
+            let mut pod = Pod::default();
+            pod.metadata.name = Some(name.to_string());
+            pod.metadata.creation_timestamp = created.map(|created| {
+                let time = k8s_openapi::chrono::DateTime::parse_from_rfc3339(created).unwrap();
+                k8s_openapi::apimachinery::pkg::apis::meta::v1::Time(time.into())
+            });
+            pod
+        };
+        let workload = Workload {
+            surge_limit: Some(2),
+            ..Default::default()
+        };
+        let pods = vec![
+            pod("unknown", None),
+            pod("new", Some("2024-01-02T00:00:00Z")),
+            pod("old", Some("2024-01-01T00:00:00Z")),
+        ];
+
+        let names = workload
+            .without_surge(pods)
+            .iter()
+            .map(ResourceExt::name_any)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["old", "new"]);
+    }
+
+    #[test]
+    fn slim_node_keep_fields() {
+        let node = load_objects!("../tests/nodes.yaml", Node).remove(0);
+        let mut full = node.clone();
+        full.metadata.managed_fields = Some(vec![Default::default()]);
+        full.status.as_mut().unwrap().images = Some(vec![Default::default()]);
+
+        let slim = slim_node(full);
+        assert_eq!(slim.metadata.name, node.metadata.name);
+        assert_eq!(slim.metadata.labels, node.metadata.labels);
+        assert_eq!(slim.metadata.managed_fields, None);
+        let status = slim.status.unwrap();
+        assert_eq!(status.conditions, node.status.unwrap().conditions);
+        assert_eq!(status.images, None);
+    }
+
+    #[test]
+    fn match_selector_requirements() {
+        let labels = BTreeMap::from([
+            (String::from("app"), String::from("web")),
+            (String::from("tier"), String::from("frontend")),
+        ]);
+
+        assert!(match_selector("", &labels));
+        assert!(match_selector("app=web", &labels));
+        assert!(match_selector("app==web, tier=frontend", &labels));
+        assert!(match_selector("app=web,tier!=db", &labels));
+        assert!(match_selector("app,!debug", &labels));
+        assert!(match_selector("env!=prod", &labels));
+        assert!(!match_selector("app=db", &labels));
+        assert!(!match_selector("app=web,tier=backend", &labels));
+        assert!(!match_selector("tier!=frontend", &labels));
+        assert!(!match_selector("env", &labels));
+        assert!(!match_selector("!app", &labels));
+    }
+
+    #[test]
+    fn workload_constraints() {
+        let constraint = |topology_key: &str, max_skew: i32| TopologySpreadConstraint {
+            topology_key: topology_key.to_string(),
+            max_skew,
+            ..Default::default()
+        };
+
+        let workload = Workload::default();
+        assert_eq!(workload.max_skew("topology.kubernetes.io/zone"), None);
+        assert_eq!(workload.constraint_keys(), "none");
+
+        let workload = Workload {
+            selector: String::from("app=web"),
+            spread_constraints: vec![
+                constraint("topology.kubernetes.io/zone", 2),
+                constraint("kubernetes.io/hostname", 1),
+                constraint("topology.kubernetes.io/zone", 1),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(workload.max_skew("topology.kubernetes.io/zone"), Some(1));
+        assert_eq!(workload.max_skew("topology.kubernetes.io/region"), None);
+        assert_eq!(
+            workload.constraint_keys(),
+            "topology.kubernetes.io/zone,kubernetes.io/hostname"
+        );
+
+        let annotations = BTreeMap::from([
+            (MAX_SKEW_ANNOTATION.to_string(), String::from("3")),
+            (EXEMPT_ANNOTATION.to_string(), String::from("true")),
+        ]);
+        let workload = workload.with_annotations(&annotations);
+        assert_eq!(workload.max_skew("topology.kubernetes.io/zone"), Some(3));
+        assert_eq!(workload.max_skew("topology.kubernetes.io/region"), Some(3));
+        assert!(workload.exempt);
+    }
 }
@@ -0,0 +1,33 @@
+//! Pod count and skew per topology domain, shared by the `kubectl-topology_skew` plugin.
+//!
+//! The per-resource functions such as [`deployment::deployment`] return [`TopologyTables`],
+//! and [`Topologies::create_with_skew_calculation`] computes the skew from the topology values.
+
+pub mod all;
+pub mod arg;
+pub mod cache;
+pub mod compare;
+pub mod daemonset;
+pub mod deployment;
+pub mod fleet;
+pub mod history;
+pub mod job;
+pub mod kube;
+pub mod manifest;
+pub mod node;
+pub mod plan;
+pub mod pod;
+pub mod profile;
+pub mod query;
+pub mod report;
+pub mod schema;
+pub mod skew;
+pub mod statefulset;
+pub mod stream;
+pub mod summary;
+pub mod topology;
+pub mod view;
+
+pub use crate::kube::*;
+pub use crate::skew::*;
+pub use crate::topology::*;
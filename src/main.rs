@@ -1,49 +1,763 @@
-mod all;
-mod arg;
-mod daemonset;
-mod deployment;
-mod job;
-mod kube;
-mod node;
-mod pod;
-mod statefulset;
-mod topology;
-mod view;
-
-use crate::all::all;
-use crate::arg::{Args, SubCommand};
-use crate::daemonset::daemonset;
-use crate::deployment::deployment;
-use crate::job::job;
-use crate::kube::*;
-use crate::node::node;
-use crate::pod::pod;
-use crate::statefulset::statefulset;
-use crate::topology::*;
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use clap::Parser;
+use futures::future;
+use kubectl_topology_skew::all::all;
+use kubectl_topology_skew::arg::{
+    Args, CacheAction, CompareOptions, GenerateTarget, HistoryAction, KubeConfigOptions, LogFormat,
+    NamespaceScopeOptions, OutputFormat, OutputVersion, ResourceOptions, ScanCommand, SubCommand,
+    TableOrder, DEFAULT_CHUNK_SIZE,
+};
+use kubectl_topology_skew::compare::compare_tables;
+use kubectl_topology_skew::daemonset::daemonset;
+use kubectl_topology_skew::deployment::deployment;
+use kubectl_topology_skew::fleet::Fleet;
+use kubectl_topology_skew::job::job;
+use kubectl_topology_skew::node::node;
+use kubectl_topology_skew::pod::pod;
+use kubectl_topology_skew::profile::Profiler;
+use kubectl_topology_skew::statefulset::statefulset;
+use kubectl_topology_skew::summary::{self, summary};
+use kubectl_topology_skew::*;
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    path::Path,
+    process::ExitCode,
+    time::{Duration, SystemTime},
+};
+
+// Exit codes so that wrappers and CI can react differently to each case
+const EXIT_NOT_FOUND: u8 = 2;
+const EXIT_THRESHOLD: u8 = 3;
+const EXIT_API_ERROR: u8 = 4;
+const EXIT_PARTIAL: u8 = 5;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    pretty_env_logger::init();
+async fn main() -> ExitCode {
+    // Usage errors exit with 1 instead of clap's 2, which means not found here
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        Err(e) => {
+            let _ = e.print();
+            return match e.use_stderr() {
+                true => ExitCode::FAILURE,
+                false => ExitCode::SUCCESS,
+            };
+        }
+    };
+
+    init_logger(args.log_level(), &args.log_format);
+
+    match run(args).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            exit_code(&e)
+        }
+    }
+}
+
+fn exit_code(e: &anyhow::Error) -> ExitCode {
+    if is_not_found(e) {
+        ExitCode::from(EXIT_NOT_FOUND)
+    } else if is_api_error(e) {
+        ExitCode::from(EXIT_API_ERROR)
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+async fn run(mut args: Args) -> Result<ExitCode> {
+    let filter = Filter::new(args.min_skew(), args.min_replicas, args.domain_filter());
+    let mut output_opts = view::OutputOptions {
+        totals: false,
+        style: args.style.clone(),
+        max_width: args.max_width.or_else(view::terminal_width),
+        group_output: args.group_output.clone(),
+        output_version: args.output_version.clone(),
+    };
+
+    // Bare invocation scans all resources in the current namespace like other kubectl plugins
+    let sub = match args.sub.take() {
+        Some(sub) => sub,
+        None => {
+            eprintln!("Hint: scanning all resources, run with --help to see subcommands");
+            SubCommand::Scan(ScanCommand::All {
+                options: ResourceOptions::default(),
+                scope: NamespaceScopeOptions::default(),
+            })
+        }
+    };
+
+    if let SubCommand::Schema = sub {
+        let schema = match args.output_version {
+            OutputVersion::V1 => schema::output_schema(),
+            OutputVersion::V2 => schema::output_schema_v2(),
+        };
+        let text = match args.output {
+            OutputFormat::Yaml => serde_yaml::to_string(&schema)?,
+            _ => serde_json::to_string_pretty(&schema)?,
+        };
+        println!("{text}");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let SubCommand::Generate {
+        target: GenerateTarget::Manifests { options },
+    } = &sub
+    {
+        print!("{}", manifest::manifests(options)?);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if args.dry_run.is_some() {
+        let cli = kube_client(args.kube_options.clone()).await?;
+        let calls = plan::plan(&sub, &args, cli).await?;
+        println!("{}", view::out_plan(calls, args.output, &output_opts)?);
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    let args = Args::parse();
+    if let SubCommand::Cache {
+        action: CacheAction::Refresh,
+    } = &sub
+    {
+        let path = args
+            .kube_options
+            .node_cache
+            .clone()
+            .context("No found --node-cache to refresh")?;
+        let server = cluster_url(&args.kube_options).await?;
+        let cli = kube_client(args.kube_options).await?;
+        let node_api = cache::refresh(cli, DEFAULT_CHUNK_SIZE, &path, &server).await?;
+        if node_api.is_degraded() {
+            bail!("Fail to refresh node cache, listing nodes is forbidden");
+        }
+        let count = node_api.nodes().len();
+        println!("Refreshed node cache: {} ({count} nodes)", path.display());
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    let kopts = args.kube_options;
-    let cli = kube_client(kopts.context, kopts.cluster, kopts.user).await?;
+    if let SubCommand::History { action } = sub {
+        let text = history(
+            action,
+            args.kube_options,
+            args.ignore_not_found,
+            args.output,
+            &output_opts,
+        )
+        .await?;
+        print(
+            &text,
+            args.output_file.as_deref(),
+            args.append,
+            args.no_pager,
+        )?;
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    let topologies = match args.sub {
-        SubCommand::Pod { options } => pod(options, cli.clone()).await?,
-        SubCommand::Node { options } => node(options, cli.clone()).await?,
-        SubCommand::Deployment { options } => deployment(options, cli.clone()).await?,
-        SubCommand::StatefulSet { options } => statefulset(options, cli.clone()).await?,
-        SubCommand::DaemonSet { options } => daemonset(options, cli.clone()).await?,
-        SubCommand::Job { options } => job(options, cli.clone()).await?,
-        SubCommand::All { options } => all(options, cli.clone()).await?,
+    let sub = match sub {
+        SubCommand::Scan(sub) => sub,
+        SubCommand::Compare { options, target } => {
+            let text = compare(options, target, &args, &filter, &output_opts).await?;
+            print(
+                &text,
+                args.output_file.as_deref(),
+                args.append,
+                args.no_pager,
+            )?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        // The others are handled above
+        _ => unreachable!(),
     };
-    let text = view::out(topologies, args.output)?;
 
-    println!("{text}");
+    // The report is written to the current context only, not to each cluster of the fleet
+    if args.write_report_cr.is_some()
+        && matches!(&sub, ScanCommand::All { scope, .. } if scope.fleet.is_some())
+    {
+        bail!("The argument '--write-report-cr' cannot be used with '--fleet'");
+    }
+
+    if let Some(every) = args.every {
+        let path = args.append_to.unwrap_or_default();
+        let until = Until {
+            skew: args.until_skew,
+            violation: args.until_violation,
+        };
+        return scan_every(
+            sub,
+            args.kube_options,
+            args.ignore_not_found,
+            every,
+            &path,
+            filter,
+            until,
+        )
+        .await;
+    }
+
+    // Scanning all resources is summarized
+    let totals = matches!(sub, ScanCommand::All { .. } | ScanCommand::Summary { .. });
+    // Summary of all namespaces is reported as the health of the cluster instead of tables
+    let health = matches!(&sub, ScanCommand::Summary { scope, .. } if scope.all_namespaces);
+    // Rejected before the scan, the health has no workload to name
+    if health && args.output == OutputFormat::Name {
+        bail!("Output format name is not supported by the health summary");
+    }
+    let kube_options = args.kube_options.clone();
+    let profiler = match args.profile {
+        true => Profiler::enabled(),
+        false => Profiler::default(),
+    };
+    let mut topologies = scan_selected(sub, kube_options, args.ignore_not_found, &profiler).await?;
+
+    // Nothing is printed for a missing resource like kubectl, whatever the output format
+    if topologies.not_found {
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let skipped = !topologies.skipped.is_empty();
+    if skipped {
+        let skipped = topologies.skipped.join(", ");
+        eprintln!("Warning: skipped forbidden resources: {skipped}");
+    }
+
+    filter.apply(&mut topologies);
+
+    if args.ideal_columns {
+        topologies = topologies.with_ideal();
+    }
+
+    if let Some(thresholds) = args.thresholds() {
+        topologies = topologies.with_status(&thresholds);
+    }
+
+    if let Some(name) = &args.write_report_cr {
+        let cli = kube_client(args.kube_options).await?;
+        let tables = topologies.iter().collect::<Vec<_>>();
+        report::write_report(cli, name, &tables).await?;
+    }
+
+    // Kept in the tables for the v2 output, and reported after the results
+    let errors = topologies.errors.clone();
+    let findings = topologies.findings.clone();
+    output_opts.totals = totals;
+    let text = match health {
+        true => view::out_health(summary::health(&topologies), args.output, &output_opts)?,
+        false => view::out(topologies, args.output, args.order_tables_by, &output_opts)?,
+    };
+
+    print(
+        &text,
+        args.output_file.as_deref(),
+        args.append,
+        args.no_pager,
+    )?;
+
+    if !findings.is_empty() {
+        eprintln!("\nFindings:");
+        for finding in &findings {
+            eprintln!("  {finding}");
+        }
+    }
+
+    // Errors are reported after the partial results so that the scan is not lost
+    if !errors.is_empty() {
+        eprintln!("\nErrors:");
+        for error in &errors {
+            eprintln!("  {error}");
+        }
+    }
 
+    if args.profile {
+        eprintln!(
+            "\nProfile:\n{}",
+            view::profile_text(profiler.timings(), &output_opts)
+        );
+    }
+
+    if !errors.is_empty() || skipped {
+        return Ok(ExitCode::from(EXIT_PARTIAL));
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+// Thresholds to print only interesting tables
+#[derive(Debug, Clone)]
+struct Filter {
+    min_skew: Option<u32>,
+    min_replicas: Option<u32>,
+    domains: Option<DomainFilter>,
+}
+
+impl Filter {
+    fn new(
+        min_skew: Option<u32>,
+        min_replicas: Option<u32>,
+        domains: Option<DomainFilter>,
+    ) -> Self {
+        Self {
+            min_skew,
+            min_replicas,
+            domains,
+        }
+    }
+
+    // Domains are narrowed first so that the skew among the remaining domains is compared
+    fn apply(&self, topologies: &mut TopologyTables) {
+        if let Some(domains) = &self.domains {
+            *topologies = std::mem::take(topologies).with_domains(domains);
+        }
+
+        if let Some(min_skew) = self.min_skew {
+            topologies.retain(|table| table.max_skew() >= min_skew);
+        }
+
+        // Pods of excluded domains are not counted either
+        if let Some(min_replicas) = self.min_replicas {
+            topologies.retain(|table| table.total() >= min_replicas);
+        }
+    }
+}
+
+async fn scan_selected(
+    sub: ScanCommand,
+    kube_options: KubeConfigOptions,
+    ignore_not_found: bool,
+    profiler: &Profiler,
+) -> Result<TopologyTables> {
+    let fleet = match &sub {
+        ScanCommand::All { scope, .. } => scope.fleet.clone(),
+        _ => None,
+    };
+
+    match fleet {
+        // Clap cannot tell the conflict when --contexts is given before the subcommand
+        Some(_) if !kube_options.contexts.is_empty() => {
+            bail!("The argument '--fleet' cannot be used with '--contexts'")
+        }
+        Some(path) => scan_fleet(&path, sub, kube_options, ignore_not_found, profiler).await,
+        None if !kube_options.contexts.is_empty() => {
+            scan_contexts(sub, kube_options, ignore_not_found, profiler).await
+        }
+        None => scan(sub, kube_options, ignore_not_found, profiler).await,
+    }
+}
+
+// Conditions to stop scanning with --every, so that scripts can wait for a rebalance
+struct Until {
+    skew: Option<u32>,
+    violation: bool,
+}
+
+impl Until {
+    fn exit_code(&self, topologies: &TopologyTables) -> Option<ExitCode> {
+        if self.violation && topologies.iter().any(|table| table.is_violated()) {
+            return Some(ExitCode::from(EXIT_THRESHOLD));
+        }
+
+        let max_skew = topologies.iter().map(|table| table.max_skew()).max();
+        self.skew
+            .filter(|until| max_skew.unwrap_or_default() <= *until)
+            .map(|_| ExitCode::SUCCESS)
+    }
+}
+
+// Keeps running and appends a JSON record per interval, until interrupted or a condition is met.
+// A failed scan is logged and retried at the next interval.
+async fn scan_every(
+    sub: ScanCommand,
+    kube_options: KubeConfigOptions,
+    ignore_not_found: bool,
+    every: Duration,
+    path: &Path,
+    filter: Filter,
+    until: Until,
+) -> Result<ExitCode> {
+    // Timings of a long-running loop would grow without bound
+    let profiler = Profiler::default();
+    loop {
+        let scanned = scan_selected(
+            sub.clone(),
+            kube_options.clone(),
+            ignore_not_found,
+            &profiler,
+        );
+        match scanned.await {
+            Ok(mut topologies) => {
+                filter.apply(&mut topologies);
+                let exit_code = until.exit_code(&topologies);
+                let record = serde_json::json!({
+                    "time": humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+                    "tables": topologies.into_ordered(&TableOrder::Name),
+                });
+                view::write_file(&serde_json::to_string(&record)?, path, true)?;
+
+                if let Some(exit_code) = exit_code {
+                    return Ok(exit_code);
+                }
+            }
+            Err(e) => log::error!("Fail to scan: {e:?}"),
+        }
+
+        tokio::time::sleep(every).await;
+    }
+}
+
+fn print(text: &str, output_file: Option<&Path>, append: bool, no_pager: bool) -> Result<()> {
+    match output_file {
+        Some(path) => view::write_file(text, path, append)?,
+        None if !no_pager && io::stdout().is_terminal() => view::page(text)?,
+        None => println!("{text}"),
+    }
     Ok(())
 }
+
+async fn history(
+    action: HistoryAction,
+    kube_options: KubeConfigOptions,
+    ignore_not_found: bool,
+    output: OutputFormat,
+    output_opts: &view::OutputOptions,
+) -> Result<String> {
+    match action {
+        HistoryAction::Record {
+            options,
+            scope,
+            store,
+        } => {
+            let dir = history::store_dir(store.store.as_deref())?;
+            let sub = ScanCommand::All {
+                options: *options,
+                scope: *scope,
+            };
+            let tables = scan(sub, kube_options, ignore_not_found, &Profiler::default()).await?;
+            let path = history::record(&dir, &tables)?;
+            Ok(format!("Recorded snapshot: {}", path.display()))
+        }
+        HistoryAction::Show { store, last } => {
+            let dir = history::store_dir(store.store.as_deref())?;
+            let snapshots = history::load(&dir, last)?;
+            view::out_history(history::trends(&snapshots), output, output_opts)
+        }
+    }
+}
+
+async fn compare(
+    options: CompareOptions,
+    target: ScanCommand,
+    args: &Args,
+    filter: &Filter,
+    output_opts: &view::OutputOptions,
+) -> Result<String> {
+    let ignore_not_found = args.ignore_not_found;
+    let scan_context = |context: &str| {
+        let kube_options = KubeConfigOptions {
+            context: Some(context.to_string()),
+            contexts: Vec::new(),
+            // A node cache is of a single cluster
+            node_cache: None,
+            ..args.kube_options.clone()
+        };
+        let context = context.to_string();
+        let target = target.clone();
+        async move {
+            scan(target, kube_options, ignore_not_found, &Profiler::default())
+                .await
+                .with_context(|| format!("Fail to scan context: {context}"))
+        }
+    };
+
+    let (context_a, context_b) = (&options.context_a, &options.context_b);
+    let (mut tables_a, mut tables_b) =
+        future::try_join(scan_context(context_a), scan_context(context_b)).await?;
+
+    // Thresholds apply to each context, so a table within them is shown empty on that side
+    filter.apply(&mut tables_a);
+    filter.apply(&mut tables_b);
+
+    let mut tables = compare_tables((context_a, tables_a), (context_b, tables_b));
+    if args.order_tables_by == TableOrder::Skew {
+        tables.sort_by_key(|table| std::cmp::Reverse(table.max_skew()));
+    }
+    view::out_compare(tables, args.output.clone(), output_opts)
+}
+
+async fn scan(
+    sub: ScanCommand,
+    kube_options: KubeConfigOptions,
+    ignore_not_found: bool,
+    profiler: &Profiler,
+) -> Result<TopologyTables> {
+    let node_cache = kube_options.node_cache.clone();
+    let cache_ttl = kube_options.cache_ttl;
+    // A node cache is of a single cluster
+    let server = match &node_cache {
+        Some(_) => cluster_url(&kube_options).await?,
+        None => String::new(),
+    };
+    let cli = kube_client(kube_options).await?;
+
+    // Nodes are listed only once and shared by all subcommands
+    let chunk_size = sub.chunk_size();
+    let node_api = profiler
+        .timed("list nodes", "-", async {
+            let node_api = match &sub {
+                ScanCommand::Node { options } if options.selectors().is_some() => {
+                    let selectors = options.selectors().unwrap_or_default();
+                    CachedNodeApi::try_from_selector(cli.clone(), chunk_size, &selectors).await?
+                }
+                _ => match sub.node_selectors() {
+                    Some(selectors) => {
+                        CachedNodeApi::try_from_node_selector(cli.clone(), chunk_size, &selectors)
+                            .await?
+                    }
+                    None => match &node_cache {
+                        Some(path) => {
+                            cache::node_api(cli.clone(), chunk_size, path, &server, cache_ttl)
+                                .await?
+                        }
+                        None => CachedNodeApi::try_from(cli.clone(), chunk_size).await?,
+                    },
+                },
+            };
+            Result::<_>::Ok(node_api.with_profiler(profiler.clone()))
+        })
+        .await?;
+
+    let result = match sub {
+        ScanCommand::Pod { options, filter } => pod(options, filter, cli.clone(), &node_api).await,
+        ScanCommand::Node { options } => node(options, cli.clone(), &node_api).await,
+        ScanCommand::Deployment { options } => deployment(options, cli.clone(), &node_api).await,
+        ScanCommand::StatefulSet { options } => statefulset(options, cli.clone(), &node_api).await,
+        ScanCommand::DaemonSet { options } => daemonset(options, cli.clone(), &node_api).await,
+        ScanCommand::Job { options } => job(options, cli.clone(), &node_api).await,
+        ScanCommand::All { options, scope } => all(options, scope, cli.clone(), &node_api).await,
+        ScanCommand::Summary { options, scope } => {
+            summary(options, scope, cli.clone(), &node_api).await
+        }
+    };
+
+    let mut tables = match result {
+        Err(e) if ignore_not_found && is_not_found(&e) => not_found_tables(&e),
+        result => result?,
+    };
+
+    // Domains without pods of the selection are unknown, so the results are partial
+    if node_api.is_degraded() {
+        tables.skipped.push(String::from("v1/node"));
+    }
+
+    Ok(tables)
+}
+
+// A missing named resource prints nothing like kubectl, while a selection
+// without objects prints an empty table
+fn not_found_tables(e: &anyhow::Error) -> TopologyTables {
+    let mut tables = TopologyTables::default();
+    tables.not_found = is_missing_resource(e);
+    tables
+}
+
+// A subcommand to run against a context, with the threshold of the context
+struct ScanTarget {
+    context: String,
+    sub: ScanCommand,
+    min_skew: Option<u32>,
+}
+
+// The same subcommand runs against each context concurrently
+async fn scan_contexts(
+    sub: ScanCommand,
+    kube_options: KubeConfigOptions,
+    ignore_not_found: bool,
+    profiler: &Profiler,
+) -> Result<TopologyTables> {
+    let targets = context_names(&kube_options)?
+        .into_iter()
+        .map(|context| ScanTarget {
+            context,
+            sub: sub.clone(),
+            min_skew: None,
+        })
+        .collect::<Vec<_>>();
+
+    scan_targets(targets, kube_options, ignore_not_found, profiler).await
+}
+
+// The all subcommand runs against each cluster listed in the fleet file
+async fn scan_fleet(
+    path: &Path,
+    sub: ScanCommand,
+    kube_options: KubeConfigOptions,
+    ignore_not_found: bool,
+    profiler: &Profiler,
+) -> Result<TopologyTables> {
+    let ScanCommand::All { options, scope } = sub else {
+        bail!("Fleet file is supported only by the all subcommand");
+    };
+
+    let fleet = Fleet::read(path)?;
+    let mut targets = Vec::new();
+    for cluster in fleet.clusters {
+        let (options, scope) = cluster.all_options(&options, &scope)?;
+        targets.push(ScanTarget {
+            context: cluster.context,
+            sub: ScanCommand::All { options, scope },
+            min_skew: cluster.min_skew,
+        });
+    }
+
+    scan_targets(targets, kube_options, ignore_not_found, profiler).await
+}
+
+// Tables are prefixed with the context name to distinguish the same workload
+async fn scan_targets(
+    targets: Vec<ScanTarget>,
+    kube_options: KubeConfigOptions,
+    ignore_not_found: bool,
+    profiler: &Profiler,
+) -> Result<TopologyTables> {
+    let scan_target = |target: ScanTarget| {
+        let kube_options = KubeConfigOptions {
+            context: Some(target.context.clone()),
+            contexts: Vec::new(),
+            // A node cache is of a single cluster
+            node_cache: None,
+            ..kube_options.clone()
+        };
+        async move {
+            let context = target.context;
+            let mut tables = scan(target.sub, kube_options, ignore_not_found, profiler)
+                .await
+                .with_context(|| format!("Fail to scan context: {context}"))?;
+
+            if let Some(min_skew) = target.min_skew {
+                tables.retain(|table| table.max_skew() >= min_skew);
+            }
+            anyhow::Ok(tables.prefixed(&context))
+        }
+    };
+    let results = future::try_join_all(targets.into_iter().map(scan_target)).await?;
+
+    let mut tables = TopologyTables::default();
+    for context_tables in results {
+        tables.append(context_tables);
+    }
+
+    Ok(tables)
+}
+
+fn init_logger(level: log::LevelFilter, format: &LogFormat) {
+    let mut logger = pretty_env_logger::formatted_builder();
+    logger.filter_level(level);
+
+    // RUST_LOG still takes precedence over -v for fine-grained filters
+    if let Ok(filters) = env::var("RUST_LOG") {
+        logger.parse_filters(&filters);
+    }
+
+    // One JSON object per line so that log pipelines can ingest it as is
+    if format == &LogFormat::Json {
+        logger.format(|buf, record| {
+            let line = serde_json::json!({
+                "time": humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{line}")
+        });
+    }
+
+    logger.init();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashSet};
+
+    use super::*;
+
+    fn topology_tables(tables: &[(&str, &[&str])]) -> TopologyTables {
+        let domains = HashSet::from([String::from("zone-a"), String::from("zone-b")]);
+        let tables = tables.iter().map(|(header, values)| {
+            let values = values.iter().map(ToString::to_string).collect();
+            TopologyTable::create(values, &domains, Some(header.to_string()))
+        });
+        TopologyTables::from(tables.collect::<BTreeSet<_>>())
+    }
+
+    fn headers(topologies: &TopologyTables) -> Vec<&str> {
+        topologies
+            .iter()
+            .filter_map(|table| table.header.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn filter_only_skewed() {
+        let tables = [
+            ("balanced", &["zone-a", "zone-b"][..]),
+            ("skewed", &["zone-a", "zone-a"][..]),
+        ];
+
+        let args = Args::parse_from(["kubectl-topology_skew", "--only-skewed", "deploy"]);
+        let mut topologies = topology_tables(&tables);
+        Filter::new(args.min_skew(), None, None).apply(&mut topologies);
+        assert_eq!(headers(&topologies), vec!["skewed"]);
+
+        let args = Args::parse_from(["kubectl-topology_skew", "--min-skew", "3", "deploy"]);
+        let mut topologies = topology_tables(&tables);
+        Filter::new(args.min_skew(), None, None).apply(&mut topologies);
+        assert!(headers(&topologies).is_empty());
+    }
+
+    #[test]
+    fn filter_min_replicas_after_domains() {
+        let tables = [
+            ("web", &["zone-a", "zone-a", "zone-b"][..]),
+            ("api", &["zone-a", "zone-b", "zone-b"][..]),
+        ];
+        let domains = DomainFilter {
+            include: vec![regex::Regex::new("^(?:zone-a)$").unwrap()],
+            exclude: Vec::new(),
+        };
+
+        // Only the pods of zone-a are counted, and a total equal to the minimum is kept
+        let mut topologies = topology_tables(&tables);
+        Filter::new(None, Some(2), Some(domains)).apply(&mut topologies);
+        assert_eq!(headers(&topologies), vec!["web"]);
+
+        let mut topologies = topology_tables(&tables);
+        Filter::new(None, Some(3), None).apply(&mut topologies);
+        assert_eq!(headers(&topologies), vec!["api", "web"]);
+    }
+
+    #[test]
+    fn not_found_missing_resource() {
+        let error = anyhow::Error::new(::kube::Error::Api(::kube::core::ErrorResponse {
+            status: String::from("Failure"),
+            message: String::from("deployments.apps \"foo\" not found"),
+            reason: String::from("NotFound"),
+            code: 404,
+        }));
+
+        // Nothing is printed, like kubectl
+        assert!(not_found_tables(&error).not_found);
+    }
+
+    #[test]
+    fn not_found_empty_selection() -> Result<()> {
+        let error = anyhow::Error::new(NotFound("objects"));
+        let tables = not_found_tables(&error);
+        assert!(!tables.not_found);
+
+        let opts = view::OutputOptions::default();
+        let json = view::out(tables, OutputFormat::Json, TableOrder::Name, &opts)?;
+        assert_eq!(json, "[]");
+
+        Ok(())
+    }
+}
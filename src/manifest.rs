@@ -0,0 +1,167 @@
+use anyhow::*;
+use serde_json::{json, Value};
+
+use crate::{
+    arg::{ManifestMode, ManifestOptions},
+    report::{REPORT_GROUP, REPORT_PLURAL},
+};
+
+const APP_NAME: &str = "kubectl-topology-skew";
+
+// Multi-document YAML to pipe into `kubectl apply -f -`, the report CRD is applied separately
+pub fn manifests(opts: &ManifestOptions) -> Result<String> {
+    let documents = match opts.mode {
+        ManifestMode::Cronjob => cronjob_manifests(opts),
+    };
+
+    let documents = documents
+        .iter()
+        .map(serde_yaml::to_string)
+        .collect::<Result<Vec<_>, _>>()
+        .context("Fail to serialize manifests")?;
+
+    Ok(documents.join("---\n"))
+}
+
+fn cronjob_manifests(opts: &ManifestOptions) -> Vec<Value> {
+    let metadata = json!({ "name": APP_NAME, "namespace": opts.namespace });
+    let subjects = json!([{
+        "kind": "ServiceAccount",
+        "name": APP_NAME,
+        "namespace": opts.namespace,
+    }]);
+
+    vec![
+        json!({
+            "apiVersion": "v1",
+            "kind": "ServiceAccount",
+            "metadata": metadata,
+        }),
+        // Read-only access to what the all subcommand scans across namespaces
+        json!({
+            "apiVersion": "rbac.authorization.k8s.io/v1",
+            "kind": "ClusterRole",
+            "metadata": { "name": APP_NAME },
+            "rules": [
+                {
+                    "apiGroups": [""],
+                    "resources": ["namespaces", "nodes", "pods"],
+                    "verbs": ["get", "list"],
+                },
+                {
+                    "apiGroups": ["apps"],
+                    "resources": ["deployments", "statefulsets", "daemonsets"],
+                    "verbs": ["get", "list"],
+                },
+                {
+                    "apiGroups": ["batch"],
+                    "resources": ["jobs"],
+                    "verbs": ["get", "list"],
+                },
+            ],
+        }),
+        json!({
+            "apiVersion": "rbac.authorization.k8s.io/v1",
+            "kind": "ClusterRoleBinding",
+            "metadata": { "name": APP_NAME },
+            "roleRef": {
+                "apiGroup": "rbac.authorization.k8s.io",
+                "kind": "ClusterRole",
+                "name": APP_NAME,
+            },
+            "subjects": subjects,
+        }),
+        // The report is written by server-side apply in the namespace of the job only
+        json!({
+            "apiVersion": "rbac.authorization.k8s.io/v1",
+            "kind": "Role",
+            "metadata": metadata,
+            "rules": [{
+                "apiGroups": [REPORT_GROUP],
+                "resources": [REPORT_PLURAL],
+                "verbs": ["get", "create", "patch"],
+            }],
+        }),
+        json!({
+            "apiVersion": "rbac.authorization.k8s.io/v1",
+            "kind": "RoleBinding",
+            "metadata": metadata,
+            "roleRef": {
+                "apiGroup": "rbac.authorization.k8s.io",
+                "kind": "Role",
+                "name": APP_NAME,
+            },
+            "subjects": subjects,
+        }),
+        json!({
+            "apiVersion": "batch/v1",
+            "kind": "CronJob",
+            "metadata": metadata,
+            "spec": {
+                "schedule": opts.schedule,
+                "concurrencyPolicy": "Forbid",
+                "jobTemplate": {
+                    "spec": {
+                        "backoffLimit": 0,
+                        "template": {
+                            "spec": {
+                                "serviceAccountName": APP_NAME,
+                                "restartPolicy": "Never",
+                                "containers": [{
+                                    "name": APP_NAME,
+                                    "image": opts.image,
+                                    "command": [
+                                        "kubectl-topology_skew",
+                                        "all",
+                                        "--all-namespaces",
+                                        "--write-report-cr",
+                                        opts.report_name,
+                                    ],
+                                }],
+                            },
+                        },
+                    },
+                },
+            },
+        }),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifests_cronjob() -> Result<()> {
+        let opts = ManifestOptions {
+            mode: ManifestMode::Cronjob,
+            image: String::from("example.com/kts:latest"),
+            namespace: String::from("monitoring"),
+            schedule: String::from("0 * * * *"),
+            report_name: String::from("daily"),
+        };
+        let text = manifests(&opts)?;
+
+        let kinds = text
+            .split("---\n")
+            .map(serde_yaml::from_str::<Value>)
+            .map(|doc| Ok(doc?["kind"].as_str().unwrap_or_default().to_string()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            kinds,
+            [
+                "ServiceAccount",
+                "ClusterRole",
+                "ClusterRoleBinding",
+                "Role",
+                "RoleBinding",
+                "CronJob"
+            ]
+        );
+        assert!(text.contains("namespace: monitoring"));
+        assert!(text.contains("schedule: 0 * * * *"));
+        assert!(text.contains("- daily"));
+
+        Ok(())
+    }
+}
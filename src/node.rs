@@ -1,29 +1,203 @@
-use std::collections::BTreeSet;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    time::SystemTime,
+};
 
 use anyhow::*;
-
-use kube::Client;
+use k8s_openapi::api::core::v1::{Node, Pod};
+use kube::{Client, ResourceExt};
 
 use crate::{
-    arg::NodeOptions, only_node_running, spreading_status, CachedNodeApi, TopologyTable,
-    TopologyTables,
+    arg::{NodeOptions, NodePreset},
+    is_cordoned, is_tainted, object_age, only_node_running, scheduled_pods, spreading_status,
+    topology_values, AgeStats, CachedNodeApi, CapacityCounts, NotFound, SchedulingCounts,
+    TopologyTable, TopologyTables,
 };
 
-pub async fn node(opts: NodeOptions, cli: Client) -> Result<TopologyTables> {
-    let node_api = CachedNodeApi::try_from(cli.clone()).await?;
-    let labels = opts.labels();
-    let nodes = node_api.list(&labels).await;
+// Karpenter, GKE, EKS, eksctl and AKS in order of precedence
+const NODEPOOL_LABELS: [&str; 6] = [
+    "karpenter.sh/nodepool",
+    "karpenter.sh/provisioner-name",
+    "cloud.google.com/gke-nodepool",
+    "eks.amazonaws.com/nodegroup",
+    "alpha.eksctl.io/nodegroup-name",
+    "kubernetes.azure.com/agentpool",
+];
+pub(crate) const UNKNOWN: &str = "<none>";
+// Same labels as the ROLES column of kubectl get nodes
+const ROLE_LABEL_PREFIX: &str = "node-role.kubernetes.io/";
+const ROLE_LABEL: &str = "kubernetes.io/role";
+
+pub async fn node(
+    opts: NodeOptions,
+    cli: Client,
+    node_api: &CachedNodeApi,
+) -> Result<TopologyTables> {
+    let selector = opts.selectors().unwrap_or_default();
+    let nodes = node_api.list(&selector).await;
     let nodes = only_node_running(nodes);
 
     if nodes.is_empty() {
-        bail!("No found nodes");
+        bail!(NotFound("nodes"));
+    }
+
+    let (topology_values, domains) = spreading_status(&nodes, &opts.topology_key, node_api).await?;
+
+    let topology_key = &opts.topology_key;
+    let tables = if opts.preset == Some(NodePreset::Nodepool) {
+        split_by(nodes, topology_key, &domains, |node| {
+            nodepool(node).unwrap_or(UNKNOWN).to_string()
+        })
+    } else if opts.show_version {
+        split_by(nodes, topology_key, &domains, |node| {
+            kubelet_version(node).unwrap_or(UNKNOWN).to_string()
+        })
+    } else if let Some(label) = &opts.split_by_node_label {
+        split_by(nodes, topology_key, &domains, |node| {
+            let value = node.labels().get(label).map(String::as_str);
+            value.unwrap_or(UNKNOWN).to_string()
+        })
+    } else if opts.by_role {
+        split_by(nodes, topology_key, &domains, role)
+    } else {
+        let scheduling = scheduling_by_domain(&nodes, topology_key);
+        let mut table =
+            TopologyTable::create(topology_values, &domains, None).with_scheduling(&scheduling);
+
+        if opts.show_age {
+            let ages = ages_by_domain(&nodes, topology_key, SystemTime::now());
+            table = table.with_ages(&ages);
+        }
+        if opts.show_capacity {
+            let pods = scheduled_pods(cli, opts.chunk_size).await?;
+            table = table.with_capacity(&capacity_by_domain(&nodes, &pods, topology_key));
+        }
+        TopologyTables::from(BTreeSet::from([table]))
+    };
+
+    // Nodes are cluster-scoped
+    Ok(tables.with_scope(topology_key, None))
+}
+
+fn scheduling_by_domain(nodes: &[Node], topology_key: &str) -> HashMap<String, SchedulingCounts> {
+    let mut counts: HashMap<String, SchedulingCounts> = HashMap::new();
+    for node in nodes {
+        let Some(domain) = node.labels().get(topology_key) else {
+            continue;
+        };
+        let count = counts.entry(domain.clone()).or_default();
+        count.cordoned += u32::from(is_cordoned(node));
+        count.tainted += u32::from(is_tainted(node));
+    }
+
+    counts
+}
+
+// Pods on nodes outside the listed ones, e.g. not ready, are not counted
+fn capacity_by_domain(
+    nodes: &[Node],
+    pods: &[Pod],
+    topology_key: &str,
+) -> HashMap<String, CapacityCounts> {
+    let mut counts: HashMap<String, CapacityCounts> = HashMap::new();
+    let mut domain_by_node = HashMap::new();
+    for node in nodes {
+        let Some(domain) = node.labels().get(topology_key) else {
+            continue;
+        };
+        domain_by_node.insert(node.name_any(), domain);
+
+        let capacity = node
+            .status
+            .as_ref()
+            .and_then(|status| status.allocatable.as_ref())
+            .and_then(|allocatable| allocatable.get("pods"))
+            .and_then(|pods| pods.0.parse::<u32>().ok())
+            .unwrap_or_default();
+        counts.entry(domain.clone()).or_default().capacity += capacity;
+    }
+
+    for pod in pods {
+        let node_name = pod.spec.as_ref().and_then(|spec| spec.node_name.as_ref());
+        if let Some(domain) = node_name.and_then(|name| domain_by_node.get(name)) {
+            counts.entry(domain.to_string()).or_default().pods += 1;
+        }
+    }
+
+    counts
+}
+
+fn ages_by_domain(
+    nodes: &[Node],
+    topology_key: &str,
+    now: SystemTime,
+) -> HashMap<String, AgeStats> {
+    let mut ages: HashMap<String, Vec<u64>> = HashMap::new();
+    for node in nodes {
+        let Some(domain) = node.labels().get(topology_key) else {
+            continue;
+        };
+        let age = object_age(node, now);
+        ages.entry(domain.clone()).or_default().push(age.as_secs());
+    }
+
+    ages.into_iter()
+        .filter_map(|(domain, ages)| AgeStats::of(ages).map(|stats| (domain, stats)))
+        .collect()
+}
+
+// One table per group, and every group is compared against all domains,
+// so a group missing from a domain has zero nodes there
+fn split_by(
+    nodes: Vec<Node>,
+    topology_key: &str,
+    domains: &HashSet<String>,
+    group_of: impl Fn(&Node) -> String,
+) -> TopologyTables {
+    let mut groups: BTreeMap<String, Vec<Node>> = BTreeMap::new();
+    for node in nodes {
+        groups.entry(group_of(&node)).or_default().push(node);
     }
 
-    let (topology_values, domains) =
-        spreading_status(&nodes, &opts.topology_key, &node_api).await?;
-    let table = TopologyTable::create(topology_values, &domains, None);
+    let tables = groups
+        .into_iter()
+        .map(|(group, nodes)| {
+            let topology_values = topology_values(topology_key, &nodes);
+            TopologyTable::create(topology_values, domains, Some(group))
+        })
+        .collect::<BTreeSet<_>>();
+
+    TopologyTables::from(tables)
+}
+
+fn nodepool(node: &Node) -> Option<&str> {
+    let labels = node.labels();
+    NODEPOOL_LABELS
+        .iter()
+        .find_map(|key| labels.get(*key).map(String::as_str))
+}
+
+// Multiple roles are joined like `control-plane,worker`
+fn role(node: &Node) -> String {
+    let labels = node.labels();
+    let roles = labels
+        .keys()
+        .filter_map(|key| key.strip_prefix(ROLE_LABEL_PREFIX))
+        .filter(|role| !role.is_empty())
+        .collect::<Vec<_>>();
+
+    match roles.is_empty() {
+        true => labels
+            .get(ROLE_LABEL)
+            .map_or(UNKNOWN, String::as_str)
+            .to_string(),
+        false => roles.join(","),
+    }
+}
 
-    Ok(TopologyTables::from(BTreeSet::from([table])))
+fn kubelet_version(node: &Node) -> Option<&str> {
+    let node_info = node.status.as_ref()?.node_info.as_ref()?;
+    Some(node_info.kubelet_version.as_str())
 }
 
 #[cfg(test)]
@@ -35,7 +209,11 @@ mod tests {
     };
     use serde::Deserialize;
 
-    use crate::{kube::tests::create_objects, Label};
+    use crate::{
+        is_not_found,
+        kube::tests::{create_objects, load_objects},
+        Label,
+    };
 
     use super::*;
     use futures::pin_mut;
@@ -59,7 +237,8 @@ mod tests {
             ..Default::default()
         };
 
-        let topology_tables = node(opts, cli).await?;
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = node(opts, cli, &node_api).await?;
         spawned.await??;
 
         for topology_table in topology_tables {
@@ -99,7 +278,8 @@ mod tests {
             ..Default::default()
         };
 
-        let topology_tables = node(opts, cli).await?;
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = node(opts, cli, &node_api).await?;
         spawned.await??;
 
         for topology_table in topology_tables {
@@ -125,6 +305,57 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn node_selector_server_side() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            let (request, send) = handle.next_request().await.unwrap();
+            let query = request.uri().query().unwrap_or_default().to_string();
+            ensure!(query.contains("labelSelector=kubernetes.io%2Fos%3Dlinux"));
+            let list = ObjectList {
+                types: TypeMeta::list::<Node>(),
+                metadata: ListMeta::default(),
+                items: load_objects!("../tests/node_selector_server_nodes.yaml", Node),
+            };
+            send.send_response(Response::builder().body(Body::from(serde_json::to_vec(&list)?))?);
+
+            // Metadata of all nodes for domain discovery
+            create_objects!(handle, "../tests/nodes.yaml", Node);
+            Ok(())
+        });
+        let cli = Client::new(mock_service, "default");
+        let opts = NodeOptions {
+            selector: vec![Label::from(("kubernetes.io/os", "linux"))],
+            ..Default::default()
+        };
+
+        let selectors = opts.selectors().unwrap();
+        let node_api =
+            CachedNodeApi::try_from_selector(cli.clone(), opts.chunk_size, &selectors).await?;
+        let topology_tables = node(opts, cli, &node_api).await?;
+        spawned.await??;
+
+        for topology_table in topology_tables {
+            let mut iter = topology_table.topologies.into_iter();
+
+            let topology1 = iter.next().unwrap();
+            assert_eq!(topology1.key, "asia-northeast1-a");
+            assert_eq!(topology1.count, 1);
+            assert_eq!(topology1.skew, 1);
+
+            let topology2 = iter.next().unwrap();
+            assert_eq!(topology2.key, "asia-northeast1-b");
+            assert_eq!(topology2.count, 0);
+
+            let topology3 = iter.next().unwrap();
+            assert_eq!(topology3.key, "asia-northeast1-c");
+            assert_eq!(topology3.count, 0);
+        }
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn node_notfound() -> Result<()> {
         let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
@@ -139,11 +370,233 @@ mod tests {
             ..Default::default()
         };
 
-        let result = node(opts, cli).await;
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let result = node(opts, cli, &node_api).await;
         spawned.await??;
 
         // TODO
         assert!(result.is_err());
+        assert!(is_not_found(&result.unwrap_err()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn node_preset_nodepool() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/node_nodepool_nodes.yaml", Node);
+            Ok(())
+        });
+        let cli = Client::new(mock_service, "default");
+        let opts = NodeOptions {
+            preset: Some(NodePreset::Nodepool),
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = node(opts, cli, &node_api).await?;
+        spawned.await??;
+
+        let counts = |table: &TopologyTable| {
+            (&table.topologies)
+                .into_iter()
+                .map(|topology| topology.count)
+                .collect::<Vec<_>>()
+        };
+        let tables = topology_tables.into_iter().collect::<Vec<_>>();
+        assert_eq!(tables.len(), 3);
+        assert_eq!(tables[0].header.as_deref(), Some("<none>"));
+        assert_eq!(counts(&tables[0]), vec![0, 0, 1]);
+        assert_eq!(tables[1].header.as_deref(), Some("default"));
+        assert_eq!(counts(&tables[1]), vec![2, 1, 0]);
+        assert_eq!(tables[2].header.as_deref(), Some("gpu"));
+        assert_eq!(counts(&tables[2]), vec![1, 0, 0]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn node_show_version() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/node_version_nodes.yaml", Node);
+            Ok(())
+        });
+        let cli = Client::new(mock_service, "default");
+        let opts = NodeOptions {
+            show_version: true,
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = node(opts, cli, &node_api).await?;
+        spawned.await??;
+
+        let summary = topology_tables
+            .into_iter()
+            .map(|table| (table.total(), table.header.unwrap_or_default()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![(2, String::from("v1.30.4")), (1, String::from("v1.31.1"))]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn node_split_by_node_label() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/node_arch_nodes.yaml", Node);
+            Ok(())
+        });
+        let cli = Client::new(mock_service, "default");
+        let opts = NodeOptions {
+            split_by_node_label: Some(String::from("kubernetes.io/arch")),
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = node(opts, cli, &node_api).await?;
+        spawned.await??;
+
+        let summary = topology_tables
+            .into_iter()
+            .map(|table| (table.total(), table.header.unwrap_or_default()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![(2, String::from("amd64")), (1, String::from("arm64"))]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn node_by_role() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/node_role_nodes.yaml", Node);
+            Ok(())
+        });
+        let cli = Client::new(mock_service, "default");
+        let opts = NodeOptions {
+            by_role: true,
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = node(opts, cli, &node_api).await?;
+        spawned.await??;
+
+        let summary = topology_tables
+            .into_iter()
+            .map(|table| {
+                (
+                    table.max_skew(),
+                    table.total(),
+                    table.header.unwrap_or_default(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (1, 1, String::from("<none>")),
+                (1, 1, String::from("control-plane")),
+                (1, 2, String::from("worker")),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn node_show_capacity() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/node_capacity_nodes.yaml", Node);
+            create_objects!(handle, "../tests/node_capacity_pods.yaml", Pod);
+            Ok(())
+        });
+        let cli = Client::new(mock_service, "default");
+        let opts = NodeOptions {
+            show_capacity: true,
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = node(opts, cli, &node_api).await?;
+        spawned.await??;
+
+        let table = topology_tables.into_iter().next().unwrap();
+        let capacity = (&table.topologies)
+            .into_iter()
+            .map(|topology| (topology.key.as_str(), topology.pods, topology.pod_capacity))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            capacity,
+            vec![
+                ("asia-northeast1-a", Some(3), Some(220)),
+                ("asia-northeast1-b", Some(1), Some(58)),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn node_ages_by_domain() -> Result<()> {
+        let nodes = load_objects!("../tests/node_age_nodes.yaml", Node);
+        let now = SystemTime::from(k8s_openapi::chrono::DateTime::parse_from_rfc3339(
+            "2024-01-11T00:00:00Z",
+        )?);
+        let day = 24 * 60 * 60;
+
+        let ages = ages_by_domain(&nodes, "topology.kubernetes.io/zone", now);
+        let stats = AgeStats {
+            min: day,
+            median: 5 * day,
+            max: 10 * day,
+        };
+        assert_eq!(ages.get("asia-northeast1-a"), Some(&stats));
+        assert_eq!(
+            ages.get("asia-northeast1-b").map(|stats| stats.max),
+            Some(day)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn node_cordoned_and_tainted() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/node_cordoned_nodes.yaml", Node);
+            Ok(())
+        });
+        let cli = Client::new(mock_service, "default");
+        let opts = NodeOptions::default();
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = node(opts, cli, &node_api).await?;
+        spawned.await??;
+
+        let topology_table = topology_tables.into_iter().next().unwrap();
+        let counts = topology_table
+            .topologies
+            .into_iter()
+            .map(|topology| (topology.count, topology.cordoned, topology.tainted))
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![(1, Some(0), Some(0)), (2, Some(1), Some(1))]);
 
         Ok(())
     }
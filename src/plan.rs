@@ -0,0 +1,290 @@
+use anyhow::*;
+use k8s_openapi::api::core::v1::Namespace;
+use kube::{api::ListParams, Api, Client};
+use serde::Serialize;
+use tabled::Tabled;
+
+use crate::{
+    arg::{
+        Args, NamespaceScopeOptions, ResourceOptions, ResourceWithNameOptions, ScanCommand,
+        SubCommand,
+    },
+    report::{REPORT_GROUP, REPORT_PLURAL},
+};
+
+// Namespace resolved from the kubeconfig when the scan runs
+const CURRENT_NAMESPACE: &str = "(current)";
+const ALL_NAMESPACES: &str = "(each)";
+const CLUSTER_SCOPE: &str = "-";
+
+// An API call of a scan, as RBAC sees it, e.g. list deployments.apps
+#[derive(Debug, Tabled, Serialize, PartialEq)]
+#[tabled(rename_all = "UPPERCASE")]
+pub struct PlannedCall {
+    pub verb: String,
+    pub resource: String,
+    pub namespace: String,
+    // Name for get and patch, label selector for list
+    pub target: String,
+    // Number of calls before pagination by --chunk-size
+    pub calls: String,
+}
+
+impl PlannedCall {
+    fn new(verb: &str, resource: &str, namespace: &str, target: &str, calls: &str) -> Self {
+        let or_none = |s: &str| match s.is_empty() {
+            true => String::from("-"),
+            false => s.to_string(),
+        };
+        Self {
+            verb: verb.to_string(),
+            resource: resource.to_string(),
+            namespace: namespace.to_string(),
+            target: or_none(target),
+            calls: calls.to_string(),
+        }
+    }
+}
+
+// Calls are listed in the order they are made. The API server is only asked
+// for the number of namespaces, with a single item list
+pub async fn plan(sub: &SubCommand, args: &Args, cli: Client) -> Result<Vec<PlannedCall>> {
+    let SubCommand::Scan(sub) = sub else {
+        bail!("Dry run is supported only for scans such as deploy, all and node");
+    };
+    // Each cluster is scanned with its own namespaces and nodes, which a single plan cannot show
+    if !args.kube_options.contexts.is_empty() {
+        bail!("Dry run is not supported with '--contexts'");
+    }
+    if matches!(sub, ScanCommand::All { scope, .. } if scope.fleet.is_some()) {
+        bail!("Dry run is not supported with '--fleet'");
+    }
+
+    let namespaces = match sub {
+        ScanCommand::All { scope, .. } | ScanCommand::Summary { scope, .. }
+            if scope.all_namespaces =>
+        {
+            namespace_count(cli).await?
+        }
+        _ => None,
+    };
+    let mut calls = node_calls(sub, args);
+
+    match sub {
+        ScanCommand::Pod { options, .. } => {
+            let namespace = options.namespace().unwrap_or(CURRENT_NAMESPACE);
+            let selector = options.selectors();
+            calls.push(PlannedCall::new("list", "pods", namespace, &selector, "1"));
+        }
+        ScanCommand::Deployment { options } => {
+            calls.extend(workload_calls("deployments.apps", options))
+        }
+        ScanCommand::StatefulSet { options } => {
+            calls.extend(workload_calls("statefulsets.apps", options))
+        }
+        ScanCommand::DaemonSet { options } => {
+            calls.extend(workload_calls("daemonsets.apps", options))
+        }
+        ScanCommand::Job { options } => calls.extend(workload_calls("jobs.batch", options)),
+        ScanCommand::All { options, scope } => calls.extend(all_calls(options, scope, namespaces)),
+        ScanCommand::Summary { options, scope } => {
+            // Scanned once and measured against region and zone
+            calls.extend(all_calls(&options.resource_options(), scope, namespaces));
+        }
+        ScanCommand::Node { options } if options.show_capacity => {
+            let selector = "status.phase!=Succeeded,status.phase!=Failed";
+            calls.push(PlannedCall::new("list", "pods", "(all)", selector, "1"));
+        }
+        ScanCommand::Node { .. } => {}
+    }
+
+    if let Some(name) = &args.write_report_cr {
+        let resource = format!("{REPORT_PLURAL}.{REPORT_GROUP}");
+        calls.push(PlannedCall::new(
+            "patch",
+            &resource,
+            CURRENT_NAMESPACE,
+            name,
+            "1",
+        ));
+    }
+
+    Ok(calls)
+}
+
+fn node_calls(sub: &ScanCommand, args: &Args) -> Vec<PlannedCall> {
+    let list = |selector: &str| PlannedCall::new("list", "nodes", CLUSTER_SCOPE, selector, "1");
+
+    match (sub, sub.node_selectors()) {
+        (ScanCommand::Node { options }, _) if options.selectors().is_some() => {
+            let selector = options.selectors().unwrap_or_default();
+            // Domains are discovered from the labels of all nodes
+            vec![list(&selector), list("")]
+        }
+        (_, Some(selector)) => vec![list(&selector)],
+        (_, None) => match &args.kube_options.node_cache {
+            Some(path) => {
+                let mut call = list("");
+                call.calls = format!("0 while {} is fresh", path.display());
+                vec![call]
+            }
+            None => vec![list("")],
+        },
+    }
+}
+
+fn workload_calls(resource: &str, opts: &ResourceWithNameOptions) -> Vec<PlannedCall> {
+    let namespace = opts.namespace().unwrap_or(CURRENT_NAMESPACE);
+    let selector = opts.selectors().unwrap_or_default();
+    let workload = match opts.name() {
+        Some(name) => PlannedCall::new("get", resource, namespace, name, "1"),
+        None => PlannedCall::new("list", resource, namespace, &selector, "1"),
+    };
+    // Pods of a single workload are selected by the API server
+    let pods = match opts.name() {
+        Some(_) => PlannedCall::new("list", "pods", namespace, "(selector of the workload)", "1"),
+        None => PlannedCall::new("list", "pods", namespace, "", "1"),
+    };
+    vec![workload, pods]
+}
+
+// Namespaces in the cluster, before --exclude-namespace and --skip-system are applied.
+// None when the API server doesn't tell the remaining count
+async fn namespace_count(cli: Client) -> Result<Option<usize>> {
+    let api: Api<Namespace> = Api::all(cli);
+    let lp = ListParams::default().limit(1);
+    let list = api
+        .list_metadata(&lp)
+        .await
+        .context("Fail to count namespaces")?;
+    let remaining = list.metadata.remaining_item_count;
+    Ok(remaining.map(|remaining| list.items.len() + remaining as usize))
+}
+
+fn all_calls(
+    opts: &ResourceOptions,
+    scope: &NamespaceScopeOptions,
+    namespaces: Option<usize>,
+) -> Vec<PlannedCall> {
+    let mut calls = Vec::new();
+    let (namespace, per_namespace) = match scope.all_namespaces {
+        true => {
+            calls.push(PlannedCall::new(
+                "list",
+                "namespaces",
+                CLUSTER_SCOPE,
+                "",
+                "1",
+            ));
+            let per_namespace = match namespaces {
+                Some(count) => format!("{count} (1 per namespace)"),
+                None => String::from("1 per namespace"),
+            };
+            (ALL_NAMESPACES, per_namespace)
+        }
+        false => (
+            opts.namespace().unwrap_or(CURRENT_NAMESPACE),
+            String::from("1"),
+        ),
+    };
+
+    let selector = opts.selectors();
+    for resource in [
+        "deployments.apps",
+        "statefulsets.apps",
+        "jobs.batch",
+        "daemonsets.apps",
+    ] {
+        calls.push(PlannedCall::new(
+            "list",
+            resource,
+            namespace,
+            &selector,
+            &per_namespace,
+        ));
+    }
+    calls.push(PlannedCall::new(
+        "list",
+        "pods",
+        namespace,
+        "",
+        &per_namespace,
+    ));
+
+    calls
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use futures::pin_mut;
+    use http::{Request, Response};
+    use kube::client::Body;
+    use tower_test::mock;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn plan_all_namespaces() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            let (request, send) = handle.next_request().await.unwrap();
+            assert!(request
+                .uri()
+                .query()
+                .unwrap_or_default()
+                .contains("limit=1"));
+            let list = serde_json::json!({
+                "kind": "PartialObjectMetadataList",
+                "apiVersion": "meta.k8s.io/v1",
+                "metadata": {"continue": "next", "remainingItemCount": 41},
+                "items": [{"metadata": {"name": "default"}}],
+            });
+            send.send_response(Response::builder().body(Body::from(serde_json::to_vec(&list)?))?);
+
+            anyhow::Ok(())
+        });
+
+        let mut args = Args::parse_from([
+            "kubectl-topology_skew",
+            "all",
+            "--all-namespaces",
+            "-l",
+            "app=web",
+        ]);
+        let sub = args.sub.take().unwrap();
+        let cli = Client::new(mock_service, "default");
+        let calls = plan(&sub, &args, cli).await?;
+        spawned.await??;
+
+        let calls = calls
+            .iter()
+            .map(|call| format!("{} {} {}", call.verb, call.resource, call.calls))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            calls,
+            vec![
+                "list nodes 1",
+                "list namespaces 1",
+                "list deployments.apps 42 (1 per namespace)",
+                "list statefulsets.apps 42 (1 per namespace)",
+                "list jobs.batch 42 (1 per namespace)",
+                "list daemonsets.apps 42 (1 per namespace)",
+                "list pods 42 (1 per namespace)",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn plan_rejects_contexts() {
+        let mut args =
+            Args::parse_from(["kubectl-topology_skew", "--contexts", "prod-*", "deploy"]);
+        let sub = args.sub.take().unwrap();
+        let (mock_service, _handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let cli = Client::new(mock_service, "default");
+        assert!(plan(&sub, &args, cli).await.is_err());
+    }
+}
@@ -1,21 +1,124 @@
-use std::collections::BTreeMap;
-
-use crate::{arg::ResourceOptions, topology_table_find_by, TopologyTables};
+use crate::{
+    arg::{PodFilterOptions, ResourceOptions},
+    is_mirror_pod, is_owned_by_daemonset, match_selector, nodes_by, object_age, only_pod_running,
+    pods_in_any_phase, priority_class_name, skew_table, CachedNodeApi, LabelSelector, NotFound,
+    PhaseCounts, TopologyTables,
+};
 use anyhow::*;
-use kube::Client;
-
-pub async fn pod(opts: ResourceOptions, cli: Client) -> Result<TopologyTables> {
+use k8s_openapi::api::core::v1::{Node, Pod};
+use kube::{Client, ResourceExt};
+use std::{
+    collections::{HashMap, HashSet},
+    time::SystemTime,
+};
+
+pub async fn pod(
+    opts: ResourceOptions,
+    filter: PodFilterOptions,
+    cli: Client,
+    node_api: &CachedNodeApi,
+) -> Result<TopologyTables> {
     let namespace = opts.namespace().unwrap_or(cli.default_namespace());
     let selectors = opts.selectors();
     let topology_key = &opts.topology_key;
-    let labels_map = BTreeMap::from([(String::new(), selectors)]);
-    let use_header = false;
 
-    let tables =
-        topology_table_find_by(labels_map, namespace, topology_key, cli.clone(), use_header)
-            .await?;
+    let selector = [selectors.as_str()];
+    let pods = pods_in_any_phase(&selector, namespace, opts.chunk_size, cli.clone());
+    let pods = node_api
+        .profiler()
+        .timed("list pods", namespace, pods)
+        .await?;
+    let pods = filter_pods(pods, &filter, SystemTime::now());
+    let running = only_pod_running(pods.clone());
+    let nodes = nodes_by(&running, node_api).await?;
+
+    if nodes.is_empty() {
+        bail!(NotFound("objects"))
+    }
+    let domains = node_api.domains_of_nodes(topology_key, &nodes);
+    let mut table = skew_table(&running, &nodes, &domains, topology_key, None);
+
+    if filter.phase_columns {
+        let phases = phase_counts(&pods, topology_key, node_api).await;
+        table = table.with_phases(&phases);
+    }
+
+    if filter.node_column {
+        table = table.with_nodes(&distinct_nodes(&nodes, topology_key));
+    }
+
+    let mut tables = TopologyTables::default();
+    tables.insert(table);
+
+    Ok(tables.with_scope(topology_key, Some(namespace)))
+}
+
+// Pods not scheduled to a node yet are not counted in any domain
+async fn phase_counts(
+    pods: &[Pod],
+    topology_key: &str,
+    node_api: &CachedNodeApi,
+) -> HashMap<String, PhaseCounts> {
+    let mut phases = HashMap::<String, PhaseCounts>::new();
+
+    for pod in pods {
+        let Some(node_name) = pod.spec.as_ref().and_then(|spec| spec.node_name.as_deref()) else {
+            continue;
+        };
+        let Some(node) = node_api.get(node_name).await else {
+            continue;
+        };
+        let Some(domain) = node.labels().get(topology_key) else {
+            continue;
+        };
+
+        let counts = phases.entry(domain.clone()).or_default();
+        match pod
+            .status
+            .as_ref()
+            .and_then(|status| status.phase.as_deref())
+        {
+            Some("Running") => counts.running += 1,
+            Some("Pending") => counts.pending += 1,
+            Some("Failed") => counts.failed += 1,
+            _ => {}
+        }
+    }
+
+    phases
+}
+
+// Nodes are listed once per pod, so they are deduplicated by name
+fn distinct_nodes(nodes: &[Node], topology_key: &str) -> HashMap<String, u32> {
+    let mut counts = HashMap::<String, u32>::new();
+    let distinct = nodes
+        .iter()
+        .filter_map(|node| Some((node.labels().get(topology_key)?, node.name_any())))
+        .collect::<HashSet<_>>();
+
+    for (domain, _) in distinct {
+        *counts.entry(domain.clone()).or_default() += 1;
+    }
+
+    counts
+}
+
+fn filter_pods(pods: Vec<Pod>, filter: &PodFilterOptions, now: SystemTime) -> Vec<Pod> {
+    let annotation_selector = filter.annotation_selector.selector();
+    let keep = |pod: &Pod| {
+        let daemonset = filter.exclude_daemonsets && is_owned_by_daemonset(pod);
+        let mirror = !filter.include_mirror_pods && is_mirror_pod(pod);
+        let priority = filter.priority_class.is_empty()
+            || priority_class_name(pod)
+                .is_some_and(|name| filter.priority_class.iter().any(|class| class == name));
+        let annotated = match_selector(&annotation_selector, pod.annotations());
+        let age = object_age(pod, now);
+        let newer = filter.newer_than.is_none_or(|newer_than| age <= newer_than);
+        let older = filter.older_than.is_none_or(|older_than| age >= older_than);
+        !(daemonset || mirror) && priority && annotated && newer && older
+    };
 
-    Ok(tables)
+    pods.into_iter().filter(keep).collect::<Vec<_>>()
 }
 
 #[cfg(test)]
@@ -27,12 +130,16 @@ mod tests {
     };
     use serde::Deserialize;
 
-    use crate::{kube::tests::create_objects, Label};
+    use crate::{
+        kube::tests::{create_objects, load_objects, send_status, status_response},
+        Label, Operator,
+    };
 
     use super::*;
     use futures::pin_mut;
     use http::{Request, Response};
     use kube::client::Body;
+    use std::time::Duration;
     use tower_test::mock;
 
     #[tokio::test]
@@ -52,7 +159,8 @@ mod tests {
             ..Default::default()
         };
 
-        let topology_tables = pod(opts, cli).await?;
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = pod(opts, Default::default(), cli, &node_api).await?;
 
         let mut topology_table_iter = topology_tables.into_iter();
 
@@ -81,6 +189,348 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn pod_exclude_daemonsets() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/nodes.yaml", Node);
+            create_objects!(handle, "../tests/pod_daemonset_pods.yaml", Pod);
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceOptions {
+            namespace: Some(ns.to_owned()),
+            ..Default::default()
+        };
+        let filter = PodFilterOptions {
+            exclude_daemonsets: true,
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = pod(opts, filter, cli, &node_api).await?;
+        spawned.await??;
+
+        let topology_table = topology_tables.into_iter().next().unwrap();
+        let counts = topology_table
+            .topologies
+            .into_iter()
+            .map(|topology| (topology.key, topology.count, topology.skew))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            vec![
+                ("asia-northeast1-a".to_string(), 2, 2),
+                ("asia-northeast1-b".to_string(), 0, 0),
+                ("asia-northeast1-c".to_string(), 0, 0),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pod_exclude_mirror_pods() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/nodes.yaml", Node);
+            create_objects!(handle, "../tests/pod_mirror_pods.yaml", Pod);
+            Ok(())
+        });
+
+        let ns = "kube-system";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceOptions {
+            namespace: Some(ns.to_owned()),
+            ..Default::default()
+        };
+        let filter = PodFilterOptions {
+            include_mirror_pods: false,
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = pod(opts, filter, cli, &node_api).await?;
+        spawned.await??;
+
+        let topology_table = topology_tables.into_iter().next().unwrap();
+        assert_eq!(topology_table.total(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pod_priority_class() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/nodes.yaml", Node);
+            create_objects!(handle, "../tests/pod_priority_class_pods.yaml", Pod);
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceOptions {
+            namespace: Some(ns.to_owned()),
+            ..Default::default()
+        };
+        let filter = PodFilterOptions {
+            priority_class: vec![String::from("system-cluster-critical")],
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = pod(opts, filter, cli, &node_api).await?;
+        spawned.await??;
+
+        let topology_table = topology_tables.into_iter().next().unwrap();
+        assert_eq!(topology_table.total(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pod_node_column() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/node_capacity_nodes.yaml", Node);
+            create_objects!(handle, "../tests/pod_distinct_nodes_pods.yaml", Pod);
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceOptions {
+            namespace: Some(ns.to_owned()),
+            ..Default::default()
+        };
+        let filter = PodFilterOptions {
+            node_column: true,
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = pod(opts, filter, cli, &node_api).await?;
+        spawned.await??;
+
+        let topology_table = topology_tables.into_iter().next().unwrap();
+        let nodes = topology_table
+            .topologies
+            .into_iter()
+            .map(|topology| (topology.count, topology.nodes))
+            .collect::<Vec<_>>();
+        assert_eq!(nodes, vec![(3, Some(2)), (1, Some(1))]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pod_phase_columns() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/nodes.yaml", Node);
+            create_objects!(handle, "../tests/pod_phase_pods.yaml", Pod);
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceOptions {
+            namespace: Some(ns.to_owned()),
+            ..Default::default()
+        };
+        let filter = PodFilterOptions {
+            phase_columns: true,
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = pod(opts, filter, cli, &node_api).await?;
+        spawned.await??;
+
+        let topology_table = topology_tables.into_iter().next().unwrap();
+        let phases = topology_table
+            .topologies
+            .into_iter()
+            .map(|topology| {
+                (
+                    topology.count,
+                    topology.running,
+                    topology.pending,
+                    topology.failed,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            phases,
+            vec![
+                (1, Some(1), Some(1), Some(0)),
+                (1, Some(1), Some(0), Some(1)),
+                (0, Some(0), Some(0), Some(0)),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn filter_pods_by_age() -> Result<()> {
+        let pods = load_objects!("../tests/pod_age_pods.yaml", Pod);
+        let now = SystemTime::from(k8s_openapi::chrono::DateTime::parse_from_rfc3339(
+            "2024-01-08T00:00:00Z",
+        )?);
+        let names = |pods: Vec<Pod>| pods.iter().map(|pod| pod.name_any()).collect::<Vec<_>>();
+
+        let filter = PodFilterOptions {
+            newer_than: Some(Duration::from_secs(60 * 60)),
+            ..Default::default()
+        };
+        assert_eq!(names(filter_pods(pods.clone(), &filter, now)), vec!["new"]);
+
+        let filter = PodFilterOptions {
+            older_than: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+            ..Default::default()
+        };
+        assert_eq!(names(filter_pods(pods, &filter, now)), vec!["old"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn filter_pods_by_annotation() -> Result<()> {
+        let pods = load_objects!("../tests/pod_annotation_pods.yaml", Pod);
+        let names = |pods: Vec<Pod>| pods.iter().map(|pod| pod.name_any()).collect::<Vec<_>>();
+
+        let filter = PodFilterOptions {
+            annotation_selector: vec![
+                Label::from(("example.com/route", "internal")),
+                Label(
+                    String::from("example.com/owner"),
+                    String::from("team-b"),
+                    Operator::NotEqual,
+                ),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            names(filter_pods(pods, &filter, SystemTime::now())),
+            vec!["team-a"]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pod_forbidden_nodes() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            send_status!(handle, 403);
+            create_objects!(handle, "../tests/pod_no_options_pods.yaml", Pod);
+
+            // Nodes are fetched one by one, and node3 cannot be read either
+            let nodes = load_objects!("../tests/nodes.yaml", Node);
+            while let Some((request, send)) = handle.next_request().await {
+                let name = request.uri().path().rsplit('/').next().unwrap_or_default();
+                match nodes.iter().find(|node| node.name_any() == name) {
+                    Some(node) if name != "node3" => send.send_response(
+                        Response::builder().body(Body::from(serde_json::to_vec(node)?))?,
+                    ),
+                    _ => send.send_response(status_response!(403)),
+                }
+            }
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceOptions {
+            namespace: Some(ns.to_owned()),
+            ..Default::default()
+        };
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        assert!(node_api.is_degraded());
+        let topology_tables = pod(opts, Default::default(), cli, &node_api).await?;
+        drop(node_api);
+        spawned.await??;
+
+        // Domains are only those of the nodes hosting the pods
+        let topology_table = topology_tables.into_iter().next().unwrap();
+        let counts = topology_table
+            .topologies
+            .into_iter()
+            .map(|topology| (topology.key, topology.count, topology.skew))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            vec![
+                (String::from("asia-northeast1-a"), 7, 3),
+                (String::from("asia-northeast1-b"), 4, 0),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pod_node_selector() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            let (request, send) = handle.next_request().await.unwrap();
+            let query = request.uri().query().unwrap_or_default().to_string();
+            ensure!(query.contains("labelSelector=cloud.google.com%2Fgke-nodepool%3Dpool-a"));
+            let list = ObjectList {
+                types: TypeMeta::list::<Node>(),
+                metadata: ListMeta::default(),
+                items: load_objects!("../tests/node_pool_nodes.yaml", Node),
+            };
+            send.send_response(Response::builder().body(Body::from(serde_json::to_vec(&list)?))?);
+
+            create_objects!(handle, "../tests/pod_no_options_pods.yaml", Pod);
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = ResourceOptions {
+            namespace: Some(ns.to_owned()),
+            node_selector: vec![Label::from(("cloud.google.com/gke-nodepool", "pool-a"))],
+            ..Default::default()
+        };
+
+        let selectors = opts.node_selectors().unwrap();
+        let node_api =
+            CachedNodeApi::try_from_node_selector(cli.clone(), opts.chunk_size, &selectors).await?;
+        let topology_tables = pod(opts, Default::default(), cli, &node_api).await?;
+        spawned.await??;
+
+        // Domains and pods of node3 outside the nodepool are ignored
+        let topology_table = topology_tables.into_iter().next().unwrap();
+        let counts = topology_table
+            .topologies
+            .into_iter()
+            .map(|topology| (topology.key, topology.count, topology.skew))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            vec![
+                ("asia-northeast1-a".to_string(), 7, 3),
+                ("asia-northeast1-b".to_string(), 4, 0),
+            ]
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn pod_one_domain() -> Result<()> {
         let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
@@ -98,7 +548,8 @@ mod tests {
             ..Default::default()
         };
 
-        let topology_tables = pod(opts, cli).await?;
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = pod(opts, Default::default(), cli, &node_api).await?;
 
         let mut topology_table_iter = topology_tables.into_iter();
 
@@ -145,7 +596,8 @@ mod tests {
             ..Default::default()
         };
 
-        let topology_tables = pod(opts, cli).await?;
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = pod(opts, Default::default(), cli, &node_api).await?;
 
         let mut topology_table_iter = topology_tables.into_iter();
 
@@ -195,7 +647,8 @@ mod tests {
             ..Default::default()
         };
 
-        let topology_tables = pod(opts, cli).await?;
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = pod(opts, Default::default(), cli, &node_api).await?;
 
         let mut topology_table_iter = topology_tables.into_iter();
 
@@ -241,7 +694,8 @@ mod tests {
             ..Default::default()
         };
 
-        let result = pod(opts, cli).await;
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let result = pod(opts, Default::default(), cli, &node_api).await;
         spawned.await??;
 
         // TODO
@@ -0,0 +1,83 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tabled::Tabled;
+
+#[derive(Debug, Clone, Tabled, Serialize, PartialEq)]
+#[tabled(rename_all = "UPPERCASE")]
+pub struct Timing {
+    pub stage: String,
+    // Namespace or workload, `-` for the whole cluster
+    pub target: String,
+    #[tabled(rename = "MS")]
+    pub millis: u128,
+}
+
+// Recorded only with --profile, and shared by all namespaces scanned concurrently.
+// The default records nothing, so that embedders of the library pay nothing for it
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    timings: Option<Arc<Mutex<Vec<Timing>>>>,
+}
+
+impl Profiler {
+    pub fn enabled() -> Self {
+        Self {
+            timings: Some(Arc::default()),
+        }
+    }
+
+    pub fn record(&self, stage: &str, target: &str, elapsed: Duration) {
+        let Some(timings) = &self.timings else {
+            return;
+        };
+        let timing = Timing {
+            stage: stage.to_string(),
+            target: target.to_string(),
+            millis: elapsed.as_millis(),
+        };
+        timings.lock().unwrap().push(timing);
+    }
+
+    pub async fn timed<T>(&self, stage: &str, target: &str, fut: impl Future<Output = T>) -> T {
+        let start = Instant::now();
+        let output = fut.await;
+        self.record(stage, target, start.elapsed());
+        output
+    }
+
+    // In the order of completion
+    pub fn timings(&self) -> Vec<Timing> {
+        self.timings
+            .as_ref()
+            .map(|timings| timings.lock().unwrap().clone())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn timed_records_when_enabled() {
+        let disabled = Profiler::default();
+        let output = disabled.timed("list pods", "default", async { 1 }).await;
+        assert_eq!(output, 1);
+        assert!(disabled.timings().is_empty());
+
+        let profiler = Profiler::enabled();
+        let shared = profiler.clone();
+        shared.timed("list pods", "default", async {}).await;
+        let targets = profiler
+            .timings()
+            .into_iter()
+            .map(|timing| timing.target)
+            .collect::<Vec<_>>();
+        assert_eq!(targets, vec!["default"]);
+    }
+}
@@ -0,0 +1,227 @@
+use anyhow::*;
+use kube::Client;
+
+use crate::{
+    all::all,
+    arg::{NamespaceScopeOptions, NodeOptions, ResourceOptions, ResourceWithNameOptions},
+    daemonset::daemonset,
+    deployment::deployment,
+    job::job,
+    node::node,
+    pod::pod,
+    statefulset::statefulset,
+    CachedNodeApi, Label, Operator, TopologyTables,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Pod,
+    #[default]
+    Deployment,
+    StatefulSet,
+    DaemonSet,
+    Job,
+    All,
+    Node,
+}
+
+// Query for programmatic consumers without constructing the command line option structs
+//
+// let tables = SkewQuery::builder()
+//     .namespace("default")
+//     .kind(Kind::Deployment)
+//     .topology_key("kubernetes.io/hostname")
+//     .build()
+//     .run(cli)
+//     .await?;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkewQuery {
+    kind: Kind,
+    namespace: Option<String>,
+    name: Option<String>,
+    topology_key: String,
+    selector: Vec<Label>,
+    chunk_size: u32,
+}
+
+impl Default for SkewQuery {
+    fn default() -> Self {
+        let opts = ResourceWithNameOptions::default();
+        Self {
+            kind: Kind::default(),
+            namespace: opts.namespace,
+            name: opts.name,
+            topology_key: opts.topology_key,
+            selector: opts.selector,
+            chunk_size: opts.chunk_size,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SkewQueryBuilder {
+    query: SkewQuery,
+}
+
+impl SkewQueryBuilder {
+    pub fn kind(mut self, kind: Kind) -> Self {
+        self.query.kind = kind;
+        self
+    }
+
+    // The default namespace of the client is used if not given
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.query.namespace = Some(namespace.into());
+        self
+    }
+
+    // Only for deployment, statefulset, daemonset and job
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.query.name = Some(name.into());
+        self
+    }
+
+    pub fn topology_key(mut self, topology_key: impl Into<String>) -> Self {
+        self.query.topology_key = topology_key.into();
+        self
+    }
+
+    pub fn label(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.query
+            .selector
+            .push(Label(key.into(), value.into(), Operator::Equal));
+        self
+    }
+
+    pub fn chunk_size(mut self, chunk_size: u32) -> Self {
+        self.query.chunk_size = chunk_size;
+        self
+    }
+
+    pub fn build(self) -> SkewQuery {
+        self.query
+    }
+}
+
+impl SkewQuery {
+    pub fn builder() -> SkewQueryBuilder {
+        SkewQueryBuilder::default()
+    }
+
+    pub async fn run(&self, cli: Client) -> Result<TopologyTables> {
+        let query = self.clone();
+        let with_name = ResourceWithNameOptions {
+            namespace: query.namespace.clone(),
+            topology_key: query.topology_key.clone(),
+            selector: query.selector.clone(),
+            name: query.name.clone(),
+            chunk_size: query.chunk_size,
+            ..Default::default()
+        };
+        let resource = ResourceOptions {
+            namespace: query.namespace,
+            topology_key: query.topology_key.clone(),
+            selector: query.selector.clone(),
+            chunk_size: query.chunk_size,
+            ..Default::default()
+        };
+
+        let node_opts = NodeOptions {
+            topology_key: query.topology_key,
+            selector: query.selector,
+            chunk_size: query.chunk_size,
+            ..Default::default()
+        };
+
+        // Same as the command line, nodes are selected on the API server only for the node kind
+        let node_api = match (query.kind, node_opts.selectors()) {
+            (Kind::Node, Some(selectors)) => {
+                CachedNodeApi::try_from_selector(cli.clone(), query.chunk_size, &selectors).await?
+            }
+            _ => CachedNodeApi::try_from(cli.clone(), query.chunk_size).await?,
+        };
+
+        match query.kind {
+            Kind::Pod => pod(resource, Default::default(), cli, &node_api).await,
+            Kind::Deployment => deployment(with_name, cli, &node_api).await,
+            Kind::StatefulSet => statefulset(with_name, cli, &node_api).await,
+            Kind::DaemonSet => daemonset(with_name, cli, &node_api).await,
+            Kind::Job => job(with_name, cli, &node_api).await,
+            Kind::All => {
+                let scope = NamespaceScopeOptions::default();
+                all(resource, scope, cli, &node_api).await
+            }
+            Kind::Node => node(node_opts, cli.clone(), &node_api).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::{
+        apps::v1::Deployment,
+        core::v1::{Node, Pod},
+    };
+    use kube::{
+        api::{ListMeta, ObjectList, TypeMeta},
+        Client,
+    };
+    use serde::Deserialize;
+
+    use crate::kube::tests::create_objects;
+
+    use super::*;
+    use futures::pin_mut;
+    use http::{Request, Response};
+    use kube::client::Body;
+    use tower_test::mock;
+
+    #[test]
+    fn builder_overrides_defaults() {
+        let query = SkewQuery::builder()
+            .namespace("x")
+            .kind(Kind::Job)
+            .topology_key("kubernetes.io/hostname")
+            .label("app", "web")
+            .build();
+
+        assert_eq!(query.kind, Kind::Job);
+        assert_eq!(query.namespace.as_deref(), Some("x"));
+        assert_eq!(query.topology_key, "kubernetes.io/hostname");
+        assert_eq!(query.selector, vec![Label::from(("app", "web"))]);
+        assert_eq!(query.chunk_size, SkewQuery::default().chunk_size);
+    }
+
+    #[tokio::test]
+    async fn query_deployment() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/nodes.yaml", Node);
+            create_objects!(handle, "../tests/deploy_no_options_deploy.yaml", Deployment);
+            create_objects!(handle, "../tests/deploy_no_options_pods.yaml", Pod);
+
+            Ok(())
+        });
+
+        let cli = Client::new(mock_service, "default");
+        let topology_tables = SkewQuery::builder()
+            .namespace("default")
+            .kind(Kind::Deployment)
+            .build()
+            .run(cli)
+            .await?;
+        spawned.await??;
+
+        let headers = topology_tables
+            .iter()
+            .filter_map(|table| table.header.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            headers,
+            vec!["apps/v1/deployment/deploy1", "apps/v1/deployment/deploy2"]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::*;
+use kube::{
+    api::{ApiResource, DynamicObject, Patch, PatchParams},
+    Api, Client,
+};
+use serde_json::json;
+
+use crate::TopologyTable;
+
+// Same as manifests/topologyskewreport-crd.yaml
+pub(crate) const REPORT_GROUP: &str = "topology-skew.watawuwu.dev";
+const REPORT_VERSION: &str = "v1alpha1";
+const REPORT_KIND: &str = "TopologySkewReport";
+pub(crate) const REPORT_PLURAL: &str = "topologyskewreports";
+const FIELD_MANAGER: &str = "kubectl-topology-skew";
+
+fn report_resource() -> ApiResource {
+    ApiResource {
+        group: REPORT_GROUP.to_string(),
+        version: REPORT_VERSION.to_string(),
+        api_version: format!("{REPORT_GROUP}/{REPORT_VERSION}"),
+        kind: REPORT_KIND.to_string(),
+        plural: REPORT_PLURAL.to_string(),
+    }
+}
+
+// Created or updated by server-side apply, so that repeated runs keep the latest result
+pub async fn write_report(cli: Client, name: &str, tables: &[&TopologyTable]) -> Result<()> {
+    let resource = report_resource();
+    let api: Api<DynamicObject> = Api::default_namespaced_with(cli, &resource);
+
+    let violations = tables
+        .iter()
+        .filter(|table| table.is_violated())
+        .filter_map(|table| table.header.clone())
+        .collect::<Vec<_>>();
+    let soft_violations = tables
+        .iter()
+        .filter(|table| table.is_soft_violated())
+        .filter_map(|table| table.header.clone())
+        .collect::<Vec<_>>();
+
+    let report = json!({
+        "apiVersion": resource.api_version,
+        "kind": resource.kind,
+        "metadata": { "name": name },
+        "spec": {
+            "tables": tables,
+            "violations": violations,
+            "softViolations": soft_violations,
+        },
+    });
+
+    let params = PatchParams::apply(FIELD_MANAGER).force();
+    api.patch(name, &params, &Patch::Apply(&report))
+        .await
+        .with_context(|| format!("Fail to write report: {name}"))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use futures::pin_mut;
+    use http::{Method, Request, Response};
+    use kube::client::Body;
+    use tower_test::mock;
+
+    #[tokio::test]
+    async fn write_report_apply() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            let (request, send) = handle.next_request().await.unwrap();
+            assert_eq!(request.method(), Method::PATCH);
+            assert_eq!(
+                request.uri().path(),
+                "/apis/topology-skew.watawuwu.dev/v1alpha1/namespaces/default/topologyskewreports/report"
+            );
+
+            let body = request.into_body().collect_bytes().await?;
+            let report: serde_json::Value = serde_json::from_slice(&body)?;
+            assert_eq!(report["spec"]["violations"], json!(["app"]));
+
+            send.send_response(Response::builder().body(Body::from(body.to_vec()))?);
+            Ok(())
+        });
+
+        let domains = HashSet::from([String::from("zone-a"), String::from("zone-b")]);
+        let values = vec![String::from("zone-a"), String::from("zone-a")];
+        let table = TopologyTable::create(values, &domains, Some(String::from("app")))
+            .with_allowed(Some(1));
+
+        let cli = Client::new(mock_service, "default");
+        write_report(cli, "report", &[&table]).await?;
+        spawned.await??;
+
+        Ok(())
+    }
+}
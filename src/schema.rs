@@ -0,0 +1,274 @@
+use serde_json::{json, Value};
+
+use crate::view::{OUTPUT_API_VERSION, OUTPUT_KIND};
+
+// JSON Schema of the json/yaml output, an array of topology tables.
+// Handwritten, so it must be kept in sync with TopologyTable and Topology.
+pub fn output_schema() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "TopologyTables",
+        "type": "array",
+        "items": { "$ref": "#/$defs/TopologyTable" },
+        "$defs": {
+            "TopologyTable": {
+                "type": "object",
+                "properties": {
+                    "topologies": {
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/Topology" }
+                    },
+                    "header": {
+                        "description": "Resource name such as apps/v1/deployment/name",
+                        "type": "string"
+                    },
+                    "topology_key": {
+                        "description": "Node label the pods are spread over",
+                        "type": "string"
+                    },
+                    "namespace": {
+                        "description": "Namespace of the resources, absent for nodes",
+                        "type": "string"
+                    },
+                    "adherence": {
+                        "description": "Percentage of domains within maxSkew, only for ScheduleAnyway constraints",
+                        "type": "integer",
+                        "minimum": 0,
+                        "maximum": 100
+                    },
+                    "group": {
+                        "description": "Value of the namespace label of --aggregate-by-namespace-label",
+                        "type": "string"
+                    },
+                    "constraint": {
+                        "description": "Topology keys of all declared topologySpreadConstraints, `none` if nothing is declared",
+                        "type": "string"
+                    }
+                },
+                "required": ["topologies"],
+                "additionalProperties": false
+            },
+            "Topology": {
+                "type": "object",
+                "properties": {
+                    "key": {
+                        "description": "Topology domain, the value of the topology key label",
+                        "type": "string"
+                    },
+                    "count": { "type": "integer", "minimum": 0 },
+                    "skew": { "type": "integer", "minimum": 0 },
+                    "allowed": {
+                        "description": "maxSkew of the workload's topologySpreadConstraints, overridden by the topology-skew.dev/max-skew annotation",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "ideal": {
+                        "description": "Count of an even spread over the domains, only with --ideal-columns",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "delta": {
+                        "description": "Count minus the ideal count, only with --ideal-columns",
+                        "type": "integer"
+                    },
+                    "running": {
+                        "description": "Running pods, only with --phase-columns",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "pending": {
+                        "description": "Pending pods, only with --phase-columns",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "failed": {
+                        "description": "Failed pods, only with --phase-columns",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "nodes": {
+                        "description": "Distinct nodes the pods occupy, only with --node-column",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "min_age": {
+                        "description": "Age of the newest node in seconds, only with --show-age",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "median_age": {
+                        "description": "Median node age in seconds, only with --show-age",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "max_age": {
+                        "description": "Age of the oldest node in seconds, only with --show-age",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "cordoned": {
+                        "description": "Cordoned nodes, only in the node view",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "tainted": {
+                        "description": "Nodes with a NoSchedule taint other than cordon, only in the node view",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "pods": {
+                        "description": "Pods scheduled on the nodes, only with --show-capacity",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "pod_capacity": {
+                        "description": "Allocatable pod slots of the nodes, only with --show-capacity",
+                        "type": "integer",
+                        "minimum": 0
+                    },
+                    "status": {
+                        "description": "Severity of the skew, only with --status or thresholds",
+                        "enum": ["OK", "WARN", "CRIT"]
+                    }
+                },
+                "required": ["key", "count", "skew"],
+                "additionalProperties": false
+            }
+        }
+    })
+}
+
+// Tables nested in an object with --output-version v2
+pub fn output_schema_v2() -> Value {
+    let mut schema = output_schema();
+    let defs = schema["$defs"].take();
+
+    json!({
+        "$schema": schema["$schema"].take(),
+        "title": "TopologyTableList",
+        "type": "object",
+        "properties": {
+            "apiVersion": { "const": OUTPUT_API_VERSION },
+            "kind": { "const": OUTPUT_KIND },
+            "items": {
+                "description": "Tables, or tables by kind with --group-output kind",
+                "oneOf": [
+                    {
+                        "type": "array",
+                        "items": { "$ref": "#/$defs/TopologyTable" }
+                    },
+                    {
+                        "type": "object",
+                        "additionalProperties": {
+                            "type": "array",
+                            "items": { "$ref": "#/$defs/TopologyTable" }
+                        }
+                    }
+                ]
+            },
+            "skipped": {
+                "description": "Resources that could not be listed, e.g. forbidden by RBAC",
+                "type": "array",
+                "items": { "type": "string" }
+            },
+            "errors": {
+                "description": "Workloads that could not be evaluated",
+                "type": "array",
+                "items": { "type": "string" }
+            },
+            "findings": {
+                "description": "Workloads that cannot be spread as declared, e.g. fewer eligible domains than minDomains",
+                "type": "array",
+                "items": { "type": "string" }
+            }
+        },
+        "required": ["apiVersion", "kind", "items"],
+        "additionalProperties": false,
+        "$defs": defs
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap, HashSet};
+
+    use super::*;
+    use crate::{
+        arg::{OutputFormat, OutputVersion, TableOrder},
+        view::{out, OutputOptions},
+        AgeStats, TopologyTable, TopologyTables,
+    };
+
+    // Keys of the serialized output must be declared in the schema
+    #[test]
+    fn output_schema_in_sync() -> anyhow::Result<()> {
+        let schema = output_schema();
+        let domains = HashSet::from([String::from("zone-a")]);
+        let table = TopologyTable::create(vec![], &domains, Some(String::from("app")))
+            .with_allowed(Some(1))
+            .with_soft(true)
+            .with_constraint(String::from("zone"))
+            .with_ideal()
+            .with_phases(&Default::default())
+            .with_nodes(&Default::default())
+            .with_ages(&HashMap::from([(
+                String::from("zone-a"),
+                AgeStats::default(),
+            )]))
+            .with_scheduling(&Default::default())
+            .with_capacity(&Default::default())
+            .with_status(&Default::default());
+
+        let table = TopologyTable {
+            topology_key: Some(String::from("zone")),
+            namespace: Some(String::from("default")),
+            group: Some(String::from("payments")),
+            ..table
+        };
+
+        let output = serde_json::to_value(vec![table])?;
+        let keys = |value: &Value| {
+            let object = value.as_object().cloned().unwrap_or_default();
+            object.keys().cloned().collect::<HashSet<_>>()
+        };
+
+        let table_keys = keys(&schema["$defs"]["TopologyTable"]["properties"]);
+        assert_eq!(keys(&output[0]), table_keys);
+
+        let topology_keys = keys(&schema["$defs"]["Topology"]["properties"]);
+        assert_eq!(keys(&output[0]["topologies"][0]), topology_keys);
+
+        Ok(())
+    }
+
+    #[test]
+    fn output_schema_v2_in_sync() -> anyhow::Result<()> {
+        let schema = output_schema_v2();
+        let domains = HashSet::from([String::from("zone-a")]);
+        let table = TopologyTable::create(vec![], &domains, Some(String::from("app")));
+        let mut tables = TopologyTables::from(BTreeSet::from([table]));
+        tables.skipped = vec![String::from("batch/v1/job")];
+        tables.errors = vec![String::from("apps/v1/deployment/web: No found selector")];
+        tables.findings = vec![String::from("apps/v1/deployment/web: minDomains")];
+
+        let opts = OutputOptions {
+            output_version: OutputVersion::V2,
+            ..Default::default()
+        };
+        let output = out(tables, OutputFormat::Json, TableOrder::Name, &opts)?;
+        let output = serde_json::from_str::<Value>(&output)?;
+
+        let keys = |value: &Value| {
+            let object = value.as_object().cloned().unwrap_or_default();
+            object.keys().cloned().collect::<HashSet<_>>()
+        };
+        assert_eq!(keys(&output), keys(&schema["properties"]));
+        assert_eq!(
+            output["apiVersion"],
+            schema["properties"]["apiVersion"]["const"]
+        );
+        assert_eq!(output["items"][0]["header"], "app");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::{Duration, SystemTime},
+};
+
+use k8s_openapi::api::core::v1::{Node, NodeStatus, Pod, PodStatus};
+use kube::ResourceExt;
+
+use crate::TopologyTable;
+
+// Pure skew computation without Client, shared by the API server and any other source of objects
+
+pub fn only_pod_running(pods: Vec<Pod>) -> Vec<Pod> {
+    let is_running = |status: &PodStatus| status.phase.as_ref().map(|phase| phase == "Running");
+    let only_running = |pod: &Pod| pod.status.as_ref().and_then(is_running).unwrap_or(false);
+
+    pods.into_iter().filter(only_running).collect::<Vec<_>>()
+}
+
+pub fn only_node_running(nodes: Vec<Node>) -> Vec<Node> {
+    let is_ready = |status: &NodeStatus| {
+        status.conditions.as_ref().map(|conditions| {
+            conditions
+                .iter()
+                .any(|condi| condi.type_ == "Ready" && condi.status == "True")
+        })
+    };
+    let only_running = |node: &Node| node.status.as_ref().and_then(is_ready).is_some();
+    nodes.into_iter().filter(only_running).collect::<Vec<_>>()
+}
+
+pub fn is_owned_by_daemonset(pod: &Pod) -> bool {
+    pod.owner_references()
+        .iter()
+        .any(|owner| owner.kind == "DaemonSet")
+}
+
+// Static pods are reflected to the API server as mirror pods with this annotation
+pub fn is_mirror_pod(pod: &Pod) -> bool {
+    pod.annotations()
+        .contains_key("kubernetes.io/config.mirror")
+}
+
+pub fn priority_class_name(pod: &Pod) -> Option<&str> {
+    pod.spec
+        .as_ref()
+        .and_then(|spec| spec.priority_class_name.as_deref())
+}
+
+// Objects without a creation timestamp, or created in the future due to clock skew, are aged zero
+pub fn object_age(object: &impl ResourceExt, now: SystemTime) -> Duration {
+    object
+        .creation_timestamp()
+        .and_then(|time| now.duration_since(SystemTime::from(time.0)).ok())
+        .unwrap_or_default()
+}
+
+pub fn topology_values(topology_key: &str, nodes: &[Node]) -> Vec<String> {
+    let find_topology_value = |node: &Node| node.labels().get(topology_key).map(String::from);
+    nodes
+        .iter()
+        .filter_map(find_topology_value)
+        .collect::<Vec<_>>()
+}
+
+pub fn node_names(pods: &[Pod]) -> Vec<&str> {
+    pods.iter()
+        .filter_map(|pod| pod.spec.as_ref().and_then(|spec| spec.node_name.as_deref()))
+        .collect::<Vec<_>>()
+}
+
+// Domains are the values of the topology key among node labels
+pub fn domains_of<'a>(
+    labels_set: impl IntoIterator<Item = &'a BTreeMap<String, String>>,
+    topology_key: &str,
+) -> HashSet<String> {
+    labels_set
+        .into_iter()
+        .filter_map(|labels| labels.get(topology_key).cloned())
+        .collect::<HashSet<_>>()
+}
+
+// Running nodes hosting the pods, one node per pod
+pub fn placed_nodes(pods: &[Pod], nodes: &[Node]) -> Vec<Node> {
+    let nodes_by_name = nodes
+        .iter()
+        .map(|node| (node.name_any(), node))
+        .collect::<HashMap<_, _>>();
+
+    let placed = node_names(pods)
+        .into_iter()
+        .filter_map(|name| nodes_by_name.get(name).map(|node| (*node).clone()))
+        .collect::<Vec<_>>();
+
+    only_node_running(placed)
+}
+
+// Pods are counted in the domain of their node, among the given domains
+pub fn skew_table(
+    pods: &[Pod],
+    nodes: &[Node],
+    domains: &HashSet<String>,
+    topology_key: &str,
+    header: Option<String>,
+) -> TopologyTable {
+    let placed = placed_nodes(pods, nodes);
+    let topology_values = topology_values(topology_key, &placed);
+
+    TopologyTable::create(topology_values, domains, header)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::kube::tests::load_objects;
+
+    #[test]
+    fn skew_table_without_client() {
+        let nodes = load_objects!("../tests/nodes.yaml", Node);
+        let pods = only_pod_running(load_objects!("../tests/deploy_no_options_pods.yaml", Pod));
+        assert!(!pods.is_empty());
+
+        let topology_key = "topology.kubernetes.io/zone";
+        let domains = domains_of(nodes.iter().map(|node| node.labels()), topology_key);
+        let table = skew_table(&pods, &nodes, &domains, topology_key, None);
+
+        let counts = table
+            .topologies
+            .into_iter()
+            .map(|topology| (topology.key, topology.count))
+            .collect::<Vec<_>>();
+        assert_eq!(counts.len(), 3);
+        assert_eq!(
+            counts.iter().map(|(_, count)| count).sum::<u32>() as usize,
+            pods.len()
+        );
+    }
+}
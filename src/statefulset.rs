@@ -5,9 +5,16 @@ use itertools::*;
 use k8s_openapi::api::apps::v1::StatefulSet;
 use kube::{api::TypeMeta, Client, ResourceExt};
 
-use crate::{arg::ResourceWithNameOptions, resources, topology_table_find_by, TopologyTables};
-
-pub async fn statefulset(opts: ResourceWithNameOptions, cli: Client) -> Result<TopologyTables> {
+use crate::{
+    arg::ResourceWithNameOptions, pick_resources, resources, topology_table_find_by, CachedNodeApi,
+    NotFound, TopologyTables, Workload,
+};
+
+pub async fn statefulset(
+    opts: ResourceWithNameOptions,
+    cli: Client,
+    node_api: &CachedNodeApi,
+) -> Result<TopologyTables> {
     let name = opts.name();
     let namespace = opts.namespace().unwrap_or(cli.default_namespace());
     let selectors = opts.selectors();
@@ -15,16 +22,24 @@ pub async fn statefulset(opts: ResourceWithNameOptions, cli: Client) -> Result<T
         resources::<StatefulSet>(name, namespace, selectors.as_deref(), cli.clone()).await?;
 
     if statefulsets.is_empty() {
-        bail!("No found statefulset");
+        bail!(NotFound("statefulset"));
     }
 
-    let labels_map = labels_set_by(&statefulsets)?;
+    let statefulsets = if opts.interactive {
+        pick_resources(statefulsets)?
+    } else {
+        statefulsets
+    };
+
+    let workloads = labels_set_by(&statefulsets)?;
     let topology_key = &opts.topology_key;
     let tables = topology_table_find_by(
-        labels_map,
+        workloads,
         namespace,
         topology_key,
+        opts.chunk_size,
         cli.clone(),
+        node_api,
         name.is_none(),
     )
     .await?;
@@ -32,7 +47,7 @@ pub async fn statefulset(opts: ResourceWithNameOptions, cli: Client) -> Result<T
     Ok(tables)
 }
 
-pub fn labels_set_by(statefulsets: &[StatefulSet]) -> Result<BTreeMap<String, String>> {
+pub fn labels_set_by(statefulsets: &[StatefulSet]) -> Result<BTreeMap<String, Workload>> {
     let sts_to_labels = |sts: &StatefulSet| {
         let selector = sts
             .spec
@@ -51,7 +66,12 @@ pub fn labels_set_by(statefulsets: &[StatefulSet]) -> Result<BTreeMap<String, St
         let kind = meta.kind.to_lowercase();
         let name = format!("{}/{}/{}", api_version, kind, sts.name_any());
 
-        Ok((name, labels))
+        let template = sts.spec.as_ref().map(|spec| &spec.template);
+        let workload = Workload::new(labels, template)
+            .with_annotations(sts.annotations())
+            .with_labels(sts.labels());
+
+        Ok((name, workload))
     };
     let labels = statefulsets
         .iter()
@@ -83,10 +103,9 @@ mod tests {
         let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
         let spawned = tokio::spawn(async move {
             pin_mut!(handle);
-            create_objects!(handle, "../tests/sts_no_options_sts.yaml", StatefulSet);
             create_objects!(handle, "../tests/nodes.yaml", Node);
-            create_objects!(handle, "../tests/sts_no_options_pods1.yaml", Pod);
-            create_objects!(handle, "../tests/sts_no_options_pods2.yaml", Pod);
+            create_objects!(handle, "../tests/sts_no_options_sts.yaml", StatefulSet);
+            create_objects!(handle, "../tests/sts_no_options_pods.yaml", Pod);
 
             Ok(())
         });
@@ -98,7 +117,8 @@ mod tests {
             ..Default::default()
         };
 
-        let topology_tables = statefulset(opts, cli).await?;
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = statefulset(opts, cli, &node_api).await?;
 
         let mut topology_table_iter = topology_tables.into_iter();
 
@@ -0,0 +1,167 @@
+use anyhow::*;
+use futures::{future, stream, Stream, StreamExt};
+use k8s_openapi::api::core::v1::{Node, Pod};
+use kube::{
+    runtime::{reflector, watcher, WatchStreamExt},
+    Api, Client, ResourceExt,
+};
+
+use crate::{domains_of, only_pod_running, skew_table, TopologyTables};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Change {
+    Synced,
+    Changed,
+    Ignored,
+}
+
+impl<K> From<&watcher::Event<K>> for Change {
+    fn from(event: &watcher::Event<K>) -> Self {
+        match event {
+            watcher::Event::InitDone => Change::Synced,
+            watcher::Event::Apply(_) | watcher::Event::Delete(_) => Change::Changed,
+            watcher::Event::Init | watcher::Event::InitApply(_) => Change::Ignored,
+        }
+    }
+}
+
+// Pods and nodes are watched and the skew is recomputed from the local caches on every change,
+// so continuous consumers don't have to poll the API server.
+// Nothing is emitted until both caches have been listed once.
+pub fn skew_stream(
+    cli: Client,
+    namespace: &str,
+    selector: Option<&str>,
+    topology_key: &str,
+) -> impl Stream<Item = Result<TopologyTables>> {
+    let mut pod_config = watcher::Config::default();
+    if let Some(selector) = selector {
+        pod_config = pod_config.labels(selector);
+    }
+    let pod_api = Api::<Pod>::namespaced(cli.clone(), namespace);
+    let node_api = Api::<Node>::all(cli);
+
+    let (pod_store, pod_writer) = reflector::store();
+    let (node_store, node_writer) = reflector::store();
+
+    let pods = reflector::reflector(pod_writer, watcher(pod_api, pod_config))
+        .default_backoff()
+        .map(|event| event.map(|event| (true, Change::from(&event))));
+    let nodes = reflector::reflector(node_writer, watcher(node_api, Default::default()))
+        .default_backoff()
+        .map(|event| event.map(|event| (false, Change::from(&event))));
+
+    let topology_key = topology_key.to_string();
+    let compute = move || {
+        let pods = pod_store
+            .state()
+            .iter()
+            .map(|pod| (**pod).clone())
+            .collect();
+        let pods = only_pod_running(pods);
+        let nodes = node_store
+            .state()
+            .iter()
+            .map(|node| (**node).clone())
+            .collect::<Vec<_>>();
+
+        let mut tables = TopologyTables::default();
+        let domains = domains_of(nodes.iter().map(|node| node.labels()), &topology_key);
+        tables.insert(skew_table(&pods, &nodes, &domains, &topology_key, None));
+        tables
+    };
+
+    // (pods synced, nodes synced)
+    let synced = (false, false);
+    stream::select(pods, nodes)
+        .scan(synced, move |synced, event| {
+            let item = match event {
+                Err(e) => Some(Err(anyhow!(e).context("Fail to watch resources"))),
+                Result::Ok((is_pod, change)) => {
+                    if change == Change::Synced {
+                        match is_pod {
+                            true => synced.0 = true,
+                            false => synced.1 = true,
+                        }
+                    }
+                    let ready = synced.0 && synced.1 && change != Change::Ignored;
+                    ready.then(|| Ok(compute()))
+                }
+            };
+            future::ready(Some(item))
+        })
+        .filter_map(future::ready)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::pin_mut;
+    use http::{Request, Response};
+    use kube::{
+        api::{ListMeta, ObjectList, TypeMeta},
+        client::Body,
+    };
+    use serde::{Deserialize, Serialize};
+    use tower_test::mock;
+
+    use crate::kube::tests::load_objects;
+
+    // Watchers need the resource version of the list to start watching from
+    fn list_body<K>(items: Vec<K>) -> Result<Body>
+    where
+        K: Clone + Serialize,
+    {
+        let list = ObjectList::<K> {
+            types: TypeMeta::default(),
+            metadata: ListMeta {
+                resource_version: Some("1".to_string()),
+                ..Default::default()
+            },
+            items,
+        };
+        Ok(Body::from(serde_json::to_vec(&list)?))
+    }
+
+    #[tokio::test]
+    async fn skew_stream_after_synced() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            // Requests of both watchers interleave, so respond by path.
+            // Watch requests are left pending until the stream is dropped.
+            let mut pending = Vec::new();
+            while let Some((request, send)) = handle.next_request().await {
+                let uri = request.uri().to_string();
+                if uri.contains("watch=true") {
+                    pending.push(send);
+                    continue;
+                }
+                let body = if uri.contains("/pods") {
+                    list_body(load_objects!("../tests/deploy_no_options_pods.yaml", Pod))?
+                } else {
+                    list_body(load_objects!("../tests/nodes.yaml", Node))?
+                };
+                send.send_response(Response::builder().body(body)?);
+            }
+            anyhow::Ok(())
+        });
+
+        let cli = Client::new(mock_service, "default");
+        let mut stream = Box::pin(skew_stream(
+            cli,
+            "default",
+            None,
+            "topology.kubernetes.io/zone",
+        ));
+
+        let tables = stream.next().await.unwrap()?;
+        let table = tables.iter().next().unwrap();
+        assert!(table.total() > 0);
+        drop(stream);
+
+        spawned.await??;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::*;
+use itertools::Itertools;
+use kube::Client;
+use serde::Serialize;
+use tabled::Tabled;
+
+use crate::{
+    all::all_by_keys,
+    arg::{NamespaceScopeOptions, SummaryOptions, DEFAULT_ZONE_LABEL},
+    node::UNKNOWN,
+    CachedNodeApi, TopologyTable, TopologyTables,
+};
+
+// Keys shown by the summary, from the coarsest
+const SUMMARY_KEYS: [(&str, &str); 2] = [
+    ("region", "topology.kubernetes.io/region"),
+    ("zone", DEFAULT_ZONE_LABEL),
+];
+
+// Workloads are counted at the finest level, where the skew matters most
+const HEALTH_KEY: &str = DEFAULT_ZONE_LABEL;
+const HEALTH_WORST: usize = 5;
+
+// Short report of all namespaces for a periodic review
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct Health {
+    pub workloads: usize,
+    pub balanced: usize,
+    pub skewed: usize,
+    pub namespaces: Vec<NamespaceHealth>,
+    pub worst: Vec<WorstWorkload>,
+    pub domains: Vec<DomainCount>,
+}
+
+#[derive(Debug, Tabled, Serialize, PartialEq)]
+#[tabled(rename_all = "UPPERCASE")]
+pub struct NamespaceHealth {
+    pub namespace: String,
+    pub balanced: usize,
+    pub skewed: usize,
+}
+
+#[derive(Debug, Tabled, Serialize, PartialEq)]
+#[tabled(rename_all = "UPPERCASE")]
+pub struct WorstWorkload {
+    pub workload: String,
+    pub skew: u32,
+}
+
+// Eligible domains only, cordoned or fully tainted ones cannot take pods
+#[derive(Debug, Tabled, Serialize, PartialEq)]
+#[tabled(rename_all = "UPPERCASE")]
+pub struct DomainCount {
+    #[tabled(rename = "TOPOLOGY KEY")]
+    pub topology_key: String,
+    pub domains: usize,
+}
+
+// Same as all, but measured against each key of one scan so that both levels are shown.
+// Tables are prefixed with the level, e.g. zone/apps/v1/deployment/web
+pub async fn summary(
+    opts: SummaryOptions,
+    scope: NamespaceScopeOptions,
+    cli: Client,
+    node_api: &CachedNodeApi,
+) -> Result<TopologyTables> {
+    let topology_keys = SUMMARY_KEYS.map(|(_, topology_key)| topology_key.to_string());
+    let tables_by_key = all_by_keys(
+        opts.resource_options(),
+        scope,
+        &topology_keys,
+        cli,
+        node_api,
+    )
+    .await?;
+
+    let mut tables = TopologyTables::default();
+    for ((level, _), level_tables) in SUMMARY_KEYS.into_iter().zip(tables_by_key) {
+        tables.append(level_tables.prefixed(level));
+    }
+
+    // Left out of the domain counts of the health
+    for topology_key in topology_keys {
+        let domains = node_api.ineligible_domains(&topology_key).into_keys();
+        tables
+            .ineligible_domains
+            .insert(topology_key, domains.collect());
+    }
+
+    Ok(tables)
+}
+
+// Skewed workloads from the worst, also shown per group of --aggregate-by-namespace-label
+pub fn worst<'a>(tables: impl IntoIterator<Item = &'a TopologyTable>) -> Vec<WorstWorkload> {
+    tables
+        .into_iter()
+        .filter(|table| table.max_skew() > 0)
+        .sorted_by_key(|table| std::cmp::Reverse(table.max_skew()))
+        .take(HEALTH_WORST)
+        .map(|table| WorstWorkload {
+            workload: table.header.clone().unwrap_or_default(),
+            skew: table.max_skew(),
+        })
+        .collect()
+}
+
+// Balanced is no skew at all, same as the SUMMARY table of text output
+pub fn health(tables: &TopologyTables) -> Health {
+    let workloads = tables
+        .iter()
+        .filter(|table| table.topology_key.as_deref() == Some(HEALTH_KEY))
+        .collect::<Vec<_>>();
+    let skewed = workloads
+        .iter()
+        .filter(|table| table.max_skew() > 0)
+        .count();
+
+    let mut namespaces: BTreeMap<&str, NamespaceHealth> = BTreeMap::new();
+    for table in &workloads {
+        let namespace = table.namespace.as_deref().unwrap_or(UNKNOWN);
+        let health = namespaces
+            .entry(namespace)
+            .or_insert_with(|| NamespaceHealth {
+                namespace: namespace.to_string(),
+                balanced: 0,
+                skewed: 0,
+            });
+        match table.max_skew() {
+            0 => health.balanced += 1,
+            _ => health.skewed += 1,
+        }
+    }
+
+    let worst = worst(workloads.iter().copied());
+
+    // Domains of all tables of a key, except those where no node accepts new pods
+    let mut domains_by_key: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for table in tables.iter() {
+        let Some(topology_key) = table.topology_key.as_deref() else {
+            continue;
+        };
+        let ineligible = tables.ineligible_domains.get(topology_key);
+        let domains = (&table.topologies)
+            .into_iter()
+            .map(|topology| topology.key.as_str())
+            .filter(|domain| ineligible.is_none_or(|ineligible| !ineligible.contains(*domain)));
+        domains_by_key
+            .entry(topology_key)
+            .or_default()
+            .extend(domains);
+    }
+    let domains = domains_by_key
+        .into_iter()
+        .map(|(topology_key, domains)| DomainCount {
+            topology_key: topology_key.to_string(),
+            domains: domains.len(),
+        })
+        .collect();
+
+    Health {
+        workloads: workloads.len(),
+        balanced: workloads.len() - skewed,
+        skewed,
+        namespaces: namespaces.into_values().collect(),
+        worst,
+        domains,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::{
+        apps::v1::{DaemonSet, Deployment, StatefulSet},
+        batch::v1::Job,
+        core::v1::{Node, Pod},
+    };
+    use kube::{
+        api::{ListMeta, ObjectList, TypeMeta},
+        Client,
+    };
+    use serde::Deserialize;
+    use std::collections::{BTreeSet, HashSet};
+
+    use crate::{kube::tests::create_objects, TopologyTable};
+
+    use super::*;
+    use futures::pin_mut;
+    use http::{Request, Response};
+    use kube::client::Body;
+    use tower_test::mock;
+
+    #[test]
+    fn health_of_namespaces() {
+        let domains = HashSet::from([
+            String::from("zone-a"),
+            String::from("zone-b"),
+            String::from("zone-c"),
+        ]);
+        let table = |header: &str, namespace: &str, values: &[&str]| {
+            let values = values.iter().map(|v| v.to_string()).collect();
+            TopologyTable {
+                topology_key: Some(String::from(HEALTH_KEY)),
+                namespace: Some(namespace.to_string()),
+                ..TopologyTable::create(values, &domains, Some(header.to_string()))
+            }
+        };
+        let mut tables = TopologyTables::from(BTreeSet::from([
+            table("zone/prod/web", "prod", &["zone-a", "zone-b", "zone-c"]),
+            table("zone/prod/api", "prod", &["zone-a", "zone-a", "zone-a"]),
+            table("zone/dev/web", "dev", &["zone-a", "zone-a", "zone-b"]),
+        ]));
+        tables.ineligible_domains = BTreeMap::from([(
+            String::from(HEALTH_KEY),
+            BTreeSet::from([String::from("zone-c")]),
+        )]);
+
+        let health = health(&tables);
+        assert_eq!(health.workloads, 3);
+        assert_eq!(health.balanced, 1);
+        assert_eq!(health.skewed, 2);
+        assert_eq!(
+            health.namespaces,
+            vec![
+                NamespaceHealth {
+                    namespace: String::from("dev"),
+                    balanced: 0,
+                    skewed: 1,
+                },
+                NamespaceHealth {
+                    namespace: String::from("prod"),
+                    balanced: 1,
+                    skewed: 1,
+                },
+            ]
+        );
+        assert_eq!(
+            health.worst,
+            vec![
+                WorstWorkload {
+                    workload: String::from("zone/prod/api"),
+                    skew: 3,
+                },
+                WorstWorkload {
+                    workload: String::from("zone/dev/web"),
+                    skew: 2,
+                },
+            ]
+        );
+        assert_eq!(
+            health.domains,
+            vec![DomainCount {
+                topology_key: String::from(HEALTH_KEY),
+                domains: 2,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn summary_region_and_zone() -> Result<()> {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            create_objects!(handle, "../tests/nodes.yaml", Node);
+            create_objects!(handle, "../tests/deploy_max_skew_deploy.yaml", Deployment);
+            create_objects!(handle, "../tests/empty.yaml", StatefulSet);
+            create_objects!(handle, "../tests/empty.yaml", Job);
+            create_objects!(handle, "../tests/empty.yaml", DaemonSet);
+            create_objects!(handle, "../tests/deploy_no_options_pods.yaml", Pod);
+
+            Ok(())
+        });
+
+        let ns = "default";
+        let cli = Client::new(mock_service, ns);
+        let opts = SummaryOptions {
+            namespace: Some(ns.to_owned()),
+            ..Default::default()
+        };
+        let scope = NamespaceScopeOptions::default();
+
+        let node_api = CachedNodeApi::try_from(cli.clone(), opts.chunk_size).await?;
+        let topology_tables = summary(opts, scope, cli, &node_api).await?;
+        spawned.await??;
+
+        let tables = topology_tables
+            .iter()
+            .map(|table| {
+                (
+                    table.header.as_deref().unwrap(),
+                    (&table.topologies).into_iter().count(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tables,
+            vec![
+                ("region/apps/v1/deployment/deploy1", 1),
+                ("region/apps/v1/deployment/deploy2", 1),
+                ("zone/apps/v1/deployment/deploy1", 3),
+                ("zone/apps/v1/deployment/deploy2", 3),
+            ]
+        );
+
+        Ok(())
+    }
+}
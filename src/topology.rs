@@ -1,22 +1,191 @@
-use crate::{nodes_by, pods_by, spreading_status, CachedNodeApi};
+use crate::{
+    arg::TableOrder, nodes_by, pods_by_workloads, skew_table, CachedNodeApi, NotFound, Workload,
+};
 use anyhow::*;
-use derive_more::{Constructor, Deref, DerefMut, From, IntoIterator};
+use derive_more::{Deref, DerefMut, IntoIterator};
+use futures::{stream, StreamExt, TryStreamExt};
 use itertools::Itertools;
+use k8s_openapi::api::core::v1::{Node, Pod};
 use kube::Client;
+use regex::Regex;
 use serde::Serialize;
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt::Debug;
+use std::time::Instant;
 use tabled::Tabled;
 
-#[derive(Debug, Default, Serialize, PartialEq, PartialOrd, Deref, DerefMut, IntoIterator, From)]
-pub struct TopologyTables(BTreeSet<TopologyTable>);
+// Workloads of a namespace processed at the same time
+const WORKLOAD_CONCURRENCY: usize = 8;
 
-#[derive(Debug, Default, Serialize, PartialEq, Eq, Constructor)]
+// Not serialized as is, the v1 output is a bare array of the tables,
+// and skipped resources, errors and findings are carried by the v2 envelope
+#[derive(Debug, Default, PartialEq, PartialOrd, Deref, DerefMut, IntoIterator)]
+pub struct TopologyTables {
+    #[deref]
+    #[deref_mut]
+    #[into_iterator(owned, ref)]
+    tables: BTreeSet<TopologyTable>,
+
+    // Resources that could not be listed, e.g. forbidden by RBAC
+    pub skipped: Vec<String>,
+
+    // Workloads that failed to be evaluated, e.g. a malformed selector
+    pub errors: Vec<String>,
+
+    // Workloads that cannot be spread as declared, e.g. fewer eligible domains than minDomains
+    pub findings: Vec<String>,
+
+    // The named resource was missing and ignored with --ignore-not-found
+    pub not_found: bool,
+
+    // Domains where no node accepts new pods by topology key, recorded by the summary
+    pub ineligible_domains: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl From<BTreeSet<TopologyTable>> for TopologyTables {
+    fn from(tables: BTreeSet<TopologyTable>) -> Self {
+        Self {
+            tables,
+            ..Default::default()
+        }
+    }
+}
+
+impl TopologyTables {
+    // Headers, skipped resources and errors are prefixed, e.g. with the namespace or the context,
+    // so that the same name can be distinguished after merging
+    pub fn prefixed(self, prefix: &str) -> Self {
+        let prefix_header = |table: TopologyTable| {
+            let header = match table.header {
+                Some(name) => format!("{prefix}/{name}"),
+                None => prefix.to_string(),
+            };
+            TopologyTable {
+                header: Some(header),
+                ..table
+            }
+        };
+        let prefix_str = |s: String| format!("{prefix}/{s}");
+
+        Self {
+            tables: self.tables.into_iter().map(prefix_header).collect(),
+            skipped: self.skipped.into_iter().map(prefix_str).collect(),
+            errors: self.errors.into_iter().map(prefix_str).collect(),
+            findings: self.findings.into_iter().map(prefix_str).collect(),
+            not_found: self.not_found,
+            ineligible_domains: self.ineligible_domains,
+        }
+    }
+
+    pub fn with_scope(self, topology_key: &str, namespace: Option<&str>) -> Self {
+        let with_scope = |table: TopologyTable| TopologyTable {
+            topology_key: Some(topology_key.to_string()),
+            namespace: namespace.map(String::from),
+            ..table
+        };
+
+        Self {
+            tables: self.tables.into_iter().map(with_scope).collect(),
+            ..self
+        }
+    }
+
+    pub fn with_group(self, group: &str) -> Self {
+        let with_group = |table: TopologyTable| TopologyTable {
+            group: Some(group.to_string()),
+            ..table
+        };
+
+        Self {
+            tables: self.tables.into_iter().map(with_group).collect(),
+            ..self
+        }
+    }
+
+    pub fn with_status(self, thresholds: &Thresholds) -> Self {
+        Self {
+            tables: self
+                .tables
+                .into_iter()
+                .map(|table| table.with_status(thresholds))
+                .collect(),
+            ..self
+        }
+    }
+
+    pub fn with_ideal(self) -> Self {
+        Self {
+            tables: self
+                .tables
+                .into_iter()
+                .map(TopologyTable::with_ideal)
+                .collect(),
+            ..self
+        }
+    }
+
+    pub fn with_domains(self, filter: &DomainFilter) -> Self {
+        Self {
+            tables: self
+                .tables
+                .into_iter()
+                .map(|table| table.with_domains(filter))
+                .collect(),
+            ..self
+        }
+    }
+
+    pub fn append(&mut self, other: TopologyTables) {
+        self.tables.extend(other.tables);
+        self.skipped.extend(other.skipped);
+        self.errors.extend(other.errors);
+        self.findings.extend(other.findings);
+        for (topology_key, domains) in other.ineligible_domains {
+            self.ineligible_domains
+                .entry(topology_key)
+                .or_default()
+                .extend(domains);
+        }
+    }
+
+    pub fn into_ordered(self, order: &TableOrder) -> Vec<TopologyTable> {
+        let mut tables = self.tables.into_iter().collect::<Vec<_>>();
+
+        if order == &TableOrder::Skew {
+            // Stable sort keeps the name order between tables with the same skew,
+            // and tables of a group stay together
+            tables.sort_by_key(|table| (table.group.clone(), std::cmp::Reverse(table.max_skew())));
+        }
+
+        tables
+    }
+}
+
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
 pub struct TopologyTable {
     pub topologies: Topologies,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub header: Option<String>,
+
+    // What was measured, to be recorded in the output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topology_key: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+
+    // Percentage of domains within maxSkew, only for ScheduleAnyway constraints
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adherence: Option<u32>,
+
+    // Value of the namespace label the tables are grouped by, e.g. a team
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
+    // Topology keys of all declared topologySpreadConstraints, the same for every domain
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub constraint: Option<String>,
 }
 
 impl PartialOrd for TopologyTable {
@@ -32,6 +201,14 @@ impl Ord for TopologyTable {
 }
 
 impl TopologyTable {
+    pub fn new(topologies: Topologies, header: Option<String>) -> Self {
+        Self {
+            topologies,
+            header,
+            ..Default::default()
+        }
+    }
+
     pub fn create(
         topology_values: Vec<String>,
         domains: &HashSet<String>,
@@ -41,10 +218,231 @@ impl TopologyTable {
 
         TopologyTable::new(topologies, header)
     }
+
+    pub fn max_skew(&self) -> u32 {
+        self.topologies
+            .0
+            .iter()
+            .map(|topology| topology.skew)
+            .max()
+            .unwrap_or_default()
+    }
+
+    // Worst status among the domains, none without --status or thresholds
+    pub fn status(&self) -> Option<Status> {
+        self.topologies
+            .0
+            .iter()
+            .filter_map(|topology| topology.status)
+            .max()
+    }
+
+    pub fn total(&self) -> u32 {
+        self.topologies
+            .0
+            .iter()
+            .map(|topology| topology.count)
+            .sum()
+    }
+
+    // Skew is greater than maxSkew declared by the workload with DoNotSchedule
+    pub fn is_violated(&self) -> bool {
+        self.adherence.is_none() && self.exceeds_allowed()
+    }
+
+    // Same as is_violated but with ScheduleAnyway, where the scheduler only prefers the spread
+    pub fn is_soft_violated(&self) -> bool {
+        self.adherence.is_some() && self.exceeds_allowed()
+    }
+
+    fn exceeds_allowed(&self) -> bool {
+        let violated =
+            |topology: &Topology| topology.allowed.is_some_and(|max| topology.skew > max);
+        self.topologies.0.iter().any(violated)
+    }
+
+    // Scored instead of violated, so that soft constraints are told apart from hard ones
+    pub fn with_soft(self, soft: bool) -> Self {
+        if !soft {
+            return self;
+        }
+        let domains = self.topologies.0.len() as u32;
+        let within = self
+            .topologies
+            .0
+            .iter()
+            .filter(|topology| topology.allowed.is_none_or(|max| topology.skew <= max))
+            .count() as u32;
+        let adherence = (within * 100).checked_div(domains).unwrap_or(100);
+
+        TopologyTable {
+            adherence: Some(adherence),
+            ..self
+        }
+    }
+
+    // Skew is recalculated over the remaining domains by the same calculation as the scan
+    pub fn with_domains(self, filter: &DomainFilter) -> Self {
+        let topologies = self
+            .topologies
+            .into_iter()
+            .filter(|topology| filter.matches(&topology.key))
+            .collect::<Vec<_>>();
+        let domains = topologies
+            .iter()
+            .map(|topology| topology.key.clone())
+            .collect::<HashSet<_>>();
+        let values = topologies
+            .iter()
+            .flat_map(|topology| std::iter::repeat_n(topology.key.clone(), topology.count as usize))
+            .collect::<Vec<_>>();
+        let skews = Topologies::create_with_skew_calculation(values, &domains)
+            .into_iter()
+            .map(|topology| (topology.key, topology.skew))
+            .collect::<HashMap<_, _>>();
+        let topologies = topologies
+            .into_iter()
+            .map(|topology| Topology {
+                skew: skews[&topology.key],
+                ..topology
+            })
+            .collect::<BTreeSet<_>>();
+
+        let soft = self.adherence.is_some();
+        TopologyTable {
+            topologies: Topologies(topologies),
+            adherence: None,
+            ..self
+        }
+        .with_soft(soft)
+    }
+
+    // The remainder of an even spread goes to the most crowded domains,
+    // so that the delta is the number of pods to move
+    pub fn with_ideal(self) -> Self {
+        let domains = self.topologies.0.len() as u32;
+        let Some(base) = self.total().checked_div(domains) else {
+            return self;
+        };
+        let remainder = (self.total() % domains) as usize;
+        let crowded = self
+            .topologies
+            .0
+            .iter()
+            .sorted_by_key(|topology| std::cmp::Reverse(topology.count))
+            .take(remainder)
+            .map(|topology| topology.key.clone())
+            .collect::<HashSet<_>>();
+
+        self.map_topologies(|topology| {
+            let ideal = base + u32::from(crowded.contains(&topology.key));
+            Topology {
+                ideal: Some(ideal),
+                delta: Some(i64::from(topology.count) - i64::from(ideal)),
+                ..topology
+            }
+        })
+    }
+
+    pub fn with_allowed(self, allowed: Option<u32>) -> Self {
+        self.map_topologies(|topology| Topology {
+            allowed,
+            ..topology
+        })
+    }
+
+    pub fn with_constraint(self, constraint: String) -> Self {
+        Self {
+            constraint: Some(constraint),
+            ..self
+        }
+    }
+
+    // Domains without pods are shown with zero counts
+    pub fn with_phases(self, phases: &HashMap<String, PhaseCounts>) -> Self {
+        self.map_topologies(|topology| {
+            let counts = phases.get(&topology.key).copied().unwrap_or_default();
+            Topology {
+                running: Some(counts.running),
+                pending: Some(counts.pending),
+                failed: Some(counts.failed),
+                ..topology
+            }
+        })
+    }
+
+    // Domains without nodes are left empty
+    pub fn with_ages(self, ages: &HashMap<String, AgeStats>) -> Self {
+        self.map_topologies(|topology| {
+            let stats = ages.get(&topology.key);
+            Topology {
+                min_age: stats.map(|stats| stats.min),
+                median_age: stats.map(|stats| stats.median),
+                max_age: stats.map(|stats| stats.max),
+                ..topology
+            }
+        })
+    }
+
+    pub fn with_nodes(self, counts: &HashMap<String, u32>) -> Self {
+        self.map_topologies(|topology| {
+            let nodes = counts.get(&topology.key).copied().unwrap_or_default();
+            Topology {
+                nodes: Some(nodes),
+                ..topology
+            }
+        })
+    }
+
+    pub fn with_capacity(self, counts: &HashMap<String, CapacityCounts>) -> Self {
+        self.map_topologies(|topology| {
+            let counts = counts.get(&topology.key).copied().unwrap_or_default();
+            Topology {
+                pods: Some(counts.pods),
+                pod_capacity: Some(counts.capacity),
+                ..topology
+            }
+        })
+    }
+
+    pub fn with_scheduling(self, counts: &HashMap<String, SchedulingCounts>) -> Self {
+        self.map_topologies(|topology| {
+            let counts = counts.get(&topology.key).copied().unwrap_or_default();
+            Topology {
+                cordoned: Some(counts.cordoned),
+                tainted: Some(counts.tainted),
+                ..topology
+            }
+        })
+    }
+
+    // Exceeding a soft maxSkew is a warning unless the crit threshold is reached
+    pub fn with_status(self, thresholds: &Thresholds) -> Self {
+        let soft = self.adherence.is_some();
+        self.map_topologies(|topology| {
+            let status = match thresholds.status(&topology) {
+                Status::Crit if soft && !thresholds.is_crit(&topology) => Status::Warn,
+                status => status,
+            };
+            Topology {
+                status: Some(status),
+                ..topology
+            }
+        })
+    }
+
+    fn map_topologies(self, f: impl FnMut(Topology) -> Topology) -> Self {
+        let topologies = self.topologies.into_iter().map(f).collect::<BTreeSet<_>>();
+
+        TopologyTable {
+            topologies: Topologies(topologies),
+            ..self
+        }
+    }
 }
 
 #[derive(Debug, Default, Serialize, PartialEq, Eq, PartialOrd, Ord, IntoIterator)]
-pub struct Topologies(BTreeSet<Topology>);
+pub struct Topologies(#[into_iterator(owned, ref)] BTreeSet<Topology>);
 
 impl Topologies {
     pub fn create_with_skew_calculation(
@@ -71,7 +469,12 @@ impl Topologies {
 
         let calc = |(key, count): (String, usize)| {
             let skew = count - global_minimum;
-            Topology::new(key, count as u32, skew as u32)
+            Topology {
+                key,
+                count: count as u32,
+                skew: skew as u32,
+                ..Default::default()
+            }
         };
         let topologies = counts_by_domain
             .into_iter()
@@ -82,46 +485,424 @@ impl Topologies {
     }
 }
 
-#[derive(Debug, Tabled, Default, Serialize, PartialEq, Eq, PartialOrd, Ord, Constructor)]
+#[derive(Debug, Tabled, Default, Serialize, PartialEq, Eq, PartialOrd, Ord)]
 #[tabled(rename_all = "UPPERCASE")]
 pub struct Topology {
     #[tabled(rename = "TOPOLOGY")]
     pub key: String,
     pub count: u32,
     pub skew: u32,
+
+    // maxSkew declared by the workload's topologySpreadConstraints,
+    // or the topology-skew.dev/max-skew annotation which takes precedence
+    #[tabled(display_with = "display_option")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed: Option<u32>,
+
+    // Count of an even spread over the domains and the difference from it, only with --ideal-columns
+    #[tabled(display_with = "display_option")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ideal: Option<u32>,
+
+    #[tabled(display_with = "display_option")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delta: Option<i64>,
+
+    // Pods by phase, only with --phase-columns
+    #[tabled(display_with = "display_option")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub running: Option<u32>,
+
+    #[tabled(display_with = "display_option")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pending: Option<u32>,
+
+    #[tabled(display_with = "display_option")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed: Option<u32>,
+
+    // Distinct nodes the pods occupy, only with --node-column
+    #[tabled(display_with = "display_option")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nodes: Option<u32>,
+
+    // Node age in seconds, only with --show-age
+    #[tabled(rename = "MIN AGE", display_with = "display_age")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_age: Option<u64>,
+
+    #[tabled(rename = "MEDIAN AGE", display_with = "display_age")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub median_age: Option<u64>,
+
+    #[tabled(rename = "MAX AGE", display_with = "display_age")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+
+    // Nodes unavailable for scheduling, only in the node view
+    #[tabled(display_with = "display_option")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cordoned: Option<u32>,
+
+    #[tabled(display_with = "display_option")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tainted: Option<u32>,
+
+    // Pods scheduled on the nodes out of their allocatable pod slots, only with --show-capacity
+    #[tabled(rename = "PODS", display_with("display_capacity", self))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pods: Option<u32>,
+
+    #[tabled(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod_capacity: Option<u32>,
+
+    // Severity of the skew, only with --status or thresholds
+    #[tabled(display_with = "display_option")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Status {
+    Ok,
+    Warn,
+    Crit,
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            Status::Ok => "OK",
+            Status::Warn => "WARN",
+            Status::Crit => "CRIT",
+        };
+        write!(f, "{s}")
+    }
+}
+
+// Skew greater than or equal to a threshold, or greater than the declared maxSkew without thresholds
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    pub warn: Option<u32>,
+    pub crit: Option<u32>,
+}
+
+impl Thresholds {
+    pub fn status(&self, topology: &Topology) -> Status {
+        let reached = |threshold: Option<u32>| threshold.is_some_and(|t| topology.skew >= t);
+        let violated = topology.allowed.is_some_and(|max| topology.skew > max);
+
+        if self.is_crit(topology) || (self.crit.is_none() && violated) {
+            Status::Crit
+        } else if reached(self.warn) {
+            Status::Warn
+        } else {
+            Status::Ok
+        }
+    }
+
+    fn is_crit(&self, topology: &Topology) -> bool {
+        self.crit.is_some_and(|crit| topology.skew >= crit)
+    }
+}
+
+// Domains to analyze, all domains are included without include patterns
+#[derive(Debug, Default, Clone)]
+pub struct DomainFilter {
+    pub include: Vec<Regex>,
+    pub exclude: Vec<Regex>,
+}
+
+impl DomainFilter {
+    pub fn matches(&self, domain: &str) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|r| r.is_match(domain));
+        let excluded = self.exclude.iter().any(|r| r.is_match(domain));
+        included && !excluded
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AgeStats {
+    pub min: u64,
+    pub median: u64,
+    pub max: u64,
+}
+
+impl AgeStats {
+    pub fn of(mut ages: Vec<u64>) -> Option<Self> {
+        ages.sort_unstable();
+        let stats = AgeStats {
+            min: *ages.first()?,
+            median: ages[ages.len() / 2],
+            max: *ages.last()?,
+        };
+        Some(stats)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SchedulingCounts {
+    pub cordoned: u32,
+    pub tainted: u32,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CapacityCounts {
+    pub pods: u32,
+    pub capacity: u32,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PhaseCounts {
+    pub running: u32,
+    pub pending: u32,
+    pub failed: u32,
+}
+
+fn display_option<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(ToString::to_string).unwrap_or_default()
+}
+
+// Such as `87/110`
+fn display_capacity(topology: &Topology) -> String {
+    match (topology.pods, topology.pod_capacity) {
+        (Some(pods), Some(capacity)) => format!("{pods}/{capacity}"),
+        (pods, _) => display_option(&pods),
+    }
+}
+
+// Largest unit only, same as the AGE column of kubectl
+fn display_age(value: &Option<u64>) -> String {
+    let Some(secs) = value else {
+        return String::new();
+    };
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
 }
 
 pub async fn topology_table_find_by(
-    labels_map: BTreeMap<String, String>,
+    workloads: BTreeMap<String, Workload>,
     namespace: &str,
     topology_key: &str,
+    chunk_size: u32,
     cli: Client,
+    node_api: &CachedNodeApi,
     use_header: bool,
 ) -> Result<TopologyTables> {
     let mut tables = TopologyTables::default();
-    let node_api = CachedNodeApi::try_from(cli.clone()).await?;
+    let pods_map = pods_by_workloads(&workloads, namespace, chunk_size, cli.clone());
+    let pods_map = node_api
+        .profiler()
+        .timed("list pods", namespace, pods_map)
+        .await?;
+
+    let workloads = nodes_by_workloads(workloads, pods_map, node_api).await?;
 
-    for (name, labels) in labels_map {
-        let pods = pods_by(&[&labels], namespace, cli.clone()).await?;
-        let nodes = nodes_by(&pods, &node_api).await?;
+    for (name, workload, pods, nodes) in workloads {
+        let start = Instant::now();
 
+        // A workload scaled to zero doesn't discard the tables of the others
         if nodes.is_empty() {
-            bail!("No found objects")
+            log::info!("Skip workload without running pods: {name}");
+            continue;
         }
-        let (topology_values, domains) = spreading_status(&nodes, topology_key, &node_api).await?;
-        let header = use_header.then_some(name);
-        let table = TopologyTable::create(topology_values, &domains, header);
+        let domains = node_api.domains_of_nodes(topology_key, &nodes);
+        tables.findings.extend(min_domains_finding(
+            &name,
+            &workload,
+            topology_key,
+            &domains,
+            node_api,
+        ));
+        let header = use_header.then(|| name.clone());
+        let table = skew_table(&pods, &nodes, &domains, topology_key, header)
+            .with_allowed(workload.max_skew(topology_key))
+            .with_soft(workload.is_soft(topology_key));
+        node_api
+            .profiler()
+            .record("compute", &name, start.elapsed());
 
         tables.insert(table);
     }
 
-    Ok(tables)
+    if tables.is_empty() {
+        bail!(NotFound("objects"))
+    }
+
+    Ok(tables.with_scope(topology_key, Some(namespace)))
+}
+
+// Nodes of workloads are looked up concurrently, since nodes are fetched one by one
+// without the list permission. Results are kept in the order of the workloads
+pub async fn nodes_by_workloads(
+    workloads: BTreeMap<String, Workload>,
+    mut pods_map: BTreeMap<String, Vec<Pod>>,
+    node_api: &CachedNodeApi,
+) -> Result<Vec<(String, Workload, Vec<Pod>, Vec<Node>)>> {
+    let lookup = |(name, workload): (String, Workload)| {
+        let pods = pods_map.remove(&name).unwrap_or_default();
+        let pods = workload.without_surge(pods);
+        async move {
+            let nodes = nodes_by(&pods, node_api).await?;
+            Ok((name, workload, pods, nodes))
+        }
+    };
+
+    stream::iter(workloads)
+        .map(lookup)
+        .buffered(WORKLOAD_CONCURRENCY)
+        .try_collect()
+        .await
+}
+
+// Fewer eligible domains than minDomains make the scheduler treat the global minimum as 0.
+// Only domains with nodes are counted, a domain whose nodes are all gone is not known here
+pub fn min_domains_finding(
+    name: &str,
+    workload: &Workload,
+    topology_key: &str,
+    domains: &HashSet<String>,
+    node_api: &CachedNodeApi,
+) -> Option<String> {
+    let min_domains = workload.min_domains(topology_key)?;
+    let mut ineligible = node_api.ineligible_domains(topology_key);
+    ineligible.retain(|domain, _| domains.contains(domain));
+    let eligible = domains
+        .iter()
+        .filter(|domain| !ineligible.contains_key(*domain))
+        .count();
+    if eligible >= min_domains as usize {
+        return None;
+    }
+
+    let mut finding = format!(
+        "{name}: {eligible} eligible domains of {topology_key} are fewer than minDomains {min_domains}"
+    );
+    if !ineligible.is_empty() {
+        let reasons = ineligible
+            .iter()
+            .map(|(domain, reason)| format!("{domain} ({reason})"))
+            .join(", ");
+        finding.push_str(&format!(", ineligible: {reasons}"));
+    }
+    Some(finding)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn soft_constraint_adherence() {
+        let domains = HashSet::from([
+            String::from("zone-a"),
+            String::from("zone-b"),
+            String::from("zone-c"),
+        ]);
+        let values = ["zone-a", "zone-a", "zone-a", "zone-b"].map(String::from);
+        let table = TopologyTable::create(values.to_vec(), &domains, None).with_allowed(Some(1));
+
+        let soft = TopologyTable::create(values.to_vec(), &domains, None)
+            .with_allowed(Some(1))
+            .with_soft(true);
+        assert!(table.is_violated());
+        assert!(!soft.is_violated());
+        assert!(soft.is_soft_violated());
+        assert_eq!(soft.adherence, Some(66));
+
+        let soft = soft.with_status(&Thresholds::default());
+        assert_eq!(soft.status(), Some(Status::Warn));
+        let table = table.with_status(&Thresholds::default());
+        assert_eq!(table.status(), Some(Status::Crit));
+    }
+
+    #[test]
+    fn domains_recalculate_skew() {
+        let domains = HashSet::from([
+            String::from("asia-northeast1-a"),
+            String::from("asia-northeast1-b"),
+            String::from("us-central1-a"),
+        ]);
+        let values = [
+            "asia-northeast1-a",
+            "asia-northeast1-a",
+            "asia-northeast1-b",
+        ]
+        .map(String::from);
+        let table = TopologyTable::create(values.to_vec(), &domains, None);
+        assert_eq!(table.max_skew(), 2);
+
+        let filter = DomainFilter {
+            include: vec![Regex::new("^(?:asia-northeast1-.*)$").unwrap()],
+            exclude: Vec::new(),
+        };
+        let table = table.with_domains(&filter);
+        let skews = table
+            .topologies
+            .into_iter()
+            .map(|topology| (topology.key, topology.skew))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            skews,
+            vec![
+                (String::from("asia-northeast1-a"), 1),
+                (String::from("asia-northeast1-b"), 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn ideal_with_remainder() {
+        let domains = HashSet::from([
+            String::from("zone-a"),
+            String::from("zone-b"),
+            String::from("zone-c"),
+        ]);
+        let values = ["zone-a", "zone-a", "zone-a", "zone-a", "zone-b"].map(String::from);
+        let table = TopologyTable::create(values.to_vec(), &domains, None).with_ideal();
+
+        let ideals = table
+            .topologies
+            .into_iter()
+            .map(|topology| (topology.key, topology.ideal, topology.delta))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ideals,
+            vec![
+                (String::from("zone-a"), Some(2), Some(2)),
+                (String::from("zone-b"), Some(2), Some(-1)),
+                (String::from("zone-c"), Some(1), Some(-1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn thresholds_status() {
+        let topology = |skew, allowed| Topology {
+            skew,
+            allowed,
+            ..Default::default()
+        };
+        let thresholds = Thresholds {
+            warn: Some(1),
+            crit: Some(3),
+        };
+        assert_eq!(thresholds.status(&topology(0, None)), Status::Ok);
+        assert_eq!(thresholds.status(&topology(1, None)), Status::Warn);
+        assert_eq!(thresholds.status(&topology(3, None)), Status::Crit);
+
+        // Declared maxSkew is used without crit threshold
+        let thresholds = Thresholds::default();
+        assert_eq!(thresholds.status(&topology(2, Some(1))), Status::Crit);
+        assert_eq!(thresholds.status(&topology(1, Some(1))), Status::Ok);
+    }
+
     #[test]
     fn create_with_skew_calculation_ok() {
         let domains = HashSet::from([
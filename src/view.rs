@@ -1,32 +1,155 @@
-use crate::{arg::OutputFormat, TopologyTable, TopologyTables};
+use crate::{
+    all::AGGREGATE_HEADER,
+    arg::{GroupOutput, OutputFormat, OutputVersion, TableOrder, TableStyle},
+    compare::{CompareTable, Counts},
+    history::Trend,
+    plan::PlannedCall,
+    profile::Timing,
+    summary::{self, Health},
+    Status, Topology, TopologyTable, TopologyTables,
+};
 use anyhow::*;
+use itertools::Itertools;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::OpenOptions,
+    io::{self, BufRead, IsTerminal, Write},
+    path::Path,
+    process::{Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+};
 use tabled::{
-    settings::{object::Rows, Alignment, Border, Panel, Style},
+    builder::Builder,
+    grid::records::PeekableRecords,
+    settings::{
+        object::{Columns, Rows},
+        Alignment, Border, Disable, Panel, Style, Width,
+    },
     Table,
 };
 
-pub fn out(topologies: TopologyTables, format: OutputFormat) -> Result<String> {
+const DEFAULT_PAGER: &str = "less";
+// Same as git: quit if one screen, keep colors, don't clear the screen
+const DEFAULT_LESS: &str = "FRX";
+pub const OUTPUT_API_VERSION: &str = "topology-skew.watawuwu.dev/v2";
+pub const OUTPUT_KIND: &str = "TopologyTableList";
+
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    // Text tables get a TOTAL row and are followed by a summary of all tables
+    pub totals: bool,
+    pub style: TableStyle,
+    // Tables are wrapped and headers are truncated to fit
+    pub max_width: Option<usize>,
+    // Only for json/yaml
+    pub group_output: Option<GroupOutput>,
+    pub output_version: OutputVersion,
+}
+
+// Json/yaml output with --output-version v2
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Envelope {
+    api_version: &'static str,
+    kind: &'static str,
+    items: serde_json::Value,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    skipped: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    findings: Vec<String>,
+}
+
+pub fn out(
+    topologies: TopologyTables,
+    format: OutputFormat,
+    order: TableOrder,
+    opts: &OutputOptions,
+) -> Result<String> {
+    let skipped = topologies.skipped.clone();
+    let errors = topologies.errors.clone();
+    let findings = topologies.findings.clone();
+    let topologies = topologies.into_ordered(&order);
     let buf = match format {
-        OutputFormat::Text => text(topologies),
+        OutputFormat::Text if opts.totals => {
+            let summary = summary_text(&topologies, opts);
+            format!("{}\n{summary}", text(topologies, opts))
+        }
+        OutputFormat::Text => text(topologies, opts),
+        OutputFormat::Summary => summary_lines(topologies),
+        OutputFormat::Name => name_lines(topologies),
+        OutputFormat::Json | OutputFormat::Yaml if opts.output_version == OutputVersion::V2 => {
+            let items = match opts.group_output {
+                Some(_) => serde_json::to_value(group_by_kind(topologies))?,
+                None => serde_json::to_value(topologies)?,
+            };
+            let envelope = Envelope {
+                api_version: OUTPUT_API_VERSION,
+                kind: OUTPUT_KIND,
+                items,
+                skipped,
+                errors,
+                findings,
+            };
+            match format {
+                OutputFormat::Yaml => serde_yaml::to_string(&envelope)?,
+                _ => serde_json::to_string_pretty(&envelope)?,
+            }
+        }
+        OutputFormat::Json if opts.group_output.is_some() => {
+            serde_json::to_string_pretty(&group_by_kind(topologies))?
+        }
+        OutputFormat::Yaml if opts.group_output.is_some() => {
+            serde_yaml::to_string(&group_by_kind(topologies))?
+        }
         OutputFormat::Json => json(topologies)?,
         OutputFormat::Yaml => yaml(topologies)?,
     };
     Ok(buf)
 }
 
-pub fn text(topology_tables: TopologyTables) -> String {
-    let header_border = Border::full(' ', '─', ' ', ' ', ' ', ' ', '─', '─');
+// Kind is the second to last segment of headers such as `[namespace/]apps/v1/deployment/name`
+fn group_by_kind(topology_tables: Vec<TopologyTable>) -> BTreeMap<String, Vec<TopologyTable>> {
+    let kind_of = |table: &TopologyTable| {
+        let kind = table
+            .header
+            .as_deref()
+            .and_then(|header| header.rsplit('/').nth(1));
+        kind.unwrap_or("other").to_string()
+    };
+
+    topology_tables
+        .into_iter()
+        .fold(BTreeMap::new(), |mut groups, table| {
+            groups.entry(kind_of(&table)).or_default().push(table);
+            groups
+        })
+}
+
+pub fn text(topology_tables: Vec<TopologyTable>, opts: &OutputOptions) -> String {
+    match topology_tables.iter().any(|table| table.group.is_some()) {
+        true => grouped_text(topology_tables, opts),
+        false => tables_text(topology_tables, opts),
+    }
+}
 
+fn tables_text(topology_tables: Vec<TopologyTable>, opts: &OutputOptions) -> String {
     let collect_view_table = |mut outputs: Vec<String>, topology_table: TopologyTable| {
-        let mut table = Table::new(topology_table.topologies);
-        table.with(Style::blank());
-
-        if let Some(title) = topology_table.header {
-            table
-                .with(Panel::header(title))
-                .modify(Rows::first(), Alignment::center())
-                .modify(Rows::first(), header_border);
-        }
+        // Skew of the TOTAL row is the max skew of the table
+        let total = Topology {
+            key: String::from("TOTAL"),
+            count: topology_table.total(),
+            skew: topology_table.max_skew(),
+            ..Default::default()
+        };
+        let total = opts.totals.then_some(&total);
+        let mut table = Table::new((&topology_table.topologies).into_iter().chain(total));
+        hide_empty_columns(&mut table);
+        let title = header_title(&topology_table, &opts.style);
+        with_style(&mut table, opts, title);
 
         outputs.push(table.to_string());
         outputs
@@ -39,10 +162,778 @@ pub fn text(topology_tables: TopologyTables) -> String {
     outputs.join("\n")
 }
 
-fn json(topologies: TopologyTables) -> Result<String> {
+// A section per group such as a team, led by the worst workloads of the group.
+// Tables of a group are next to each other in both orders of --order-tables-by
+fn grouped_text(topology_tables: Vec<TopologyTable>, opts: &OutputOptions) -> String {
+    let groups = topology_tables
+        .into_iter()
+        .chunk_by(|table| table.group.clone().unwrap_or_default());
+    let groups = groups
+        .into_iter()
+        .map(|(group, tables)| (group, tables.collect::<Vec<_>>()))
+        .collect::<Vec<_>>();
+
+    let section = |(group, tables): (String, Vec<TopologyTable>)| {
+        let mut worst = Table::new(summary::worst(&tables));
+        with_style(&mut worst, opts, Some(format!("{group} WORST")));
+        format!("{worst}\n{}", tables_text(tables, opts))
+    };
+
+    groups.into_iter().map(section).join("\n")
+}
+
+// e.g. `apps/v1/deployment/web — topology.kubernetes.io/zone — ns: prod`
+fn header_title(topology_table: &TopologyTable, style: &TableStyle) -> Option<String> {
+    let header = topology_table.header.as_deref()?;
+    let mut parts = vec![header.to_string()];
+    parts.extend(topology_table.topology_key.clone());
+    parts.extend(
+        topology_table
+            .namespace
+            .as_ref()
+            .map(|ns| format!("ns: {ns}")),
+    );
+    parts.extend(
+        topology_table
+            .adherence
+            .map(|adherence| format!("adherence: {adherence}%")),
+    );
+    parts.extend(
+        topology_table
+            .constraint
+            .as_ref()
+            .map(|constraint| format!("constraint: {constraint}")),
+    );
+
+    let separator = match style {
+        TableStyle::Ascii => " - ",
+        _ => " — ",
+    };
+    Some(parts.join(separator))
+}
+
+// Header is a centered panel, underlined only in the blank style which has no borders
+fn with_style(table: &mut Table, opts: &OutputOptions, title: Option<String>) {
+    match opts.style {
+        TableStyle::Blank => table.with(Style::blank()),
+        TableStyle::Rounded => table.with(Style::rounded()),
+        TableStyle::Markdown => table.with(Style::markdown()),
+        TableStyle::Ascii => table.with(Style::ascii()),
+    };
+
+    // Columns are wrapped before the panel, which spans all of them
+    let max_width = opts.max_width.filter(|width| *width > 0);
+    if let Some(width) = max_width {
+        table.with(Width::wrap(width).keep_words(true));
+    }
+
+    if let Some(title) = title {
+        // Borders and padding on both sides take 4 columns in every style
+        let title = match max_width {
+            Some(width) => truncate(&title, width.saturating_sub(4), &opts.style),
+            None => title,
+        };
+        table
+            .with(Panel::header(title))
+            .modify(Rows::first(), Alignment::center());
+        if opts.style == TableStyle::Blank {
+            let header_border = Border::full(' ', '─', ' ', ' ', ' ', ' ', '─', '─');
+            table.modify(Rows::first(), header_border);
+        }
+    }
+}
+
+fn truncate(s: &str, width: usize, style: &TableStyle) -> String {
+    if s.chars().count() <= width {
+        return s.to_string();
+    }
+    let suffix = match style {
+        TableStyle::Ascii => "...",
+        _ => "…",
+    };
+    let kept = width.saturating_sub(suffix.chars().count());
+    let truncated = s.chars().take(kept).collect::<String>();
+    format!("{truncated}{suffix}")
+}
+
+// Only when stdout is a terminal, from `COLUMNS` which shells export to their children
+pub fn terminal_width() -> Option<usize> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    env::var("COLUMNS").ok().and_then(|c| c.parse().ok())
+}
+
+// For grep, diff and chat, the topology key is shortened to its last segment such as `zone`
+fn summary_lines(topology_tables: Vec<TopologyTable>) -> String {
+    let line = |table: TopologyTable| {
+        let mut fields = vec![table.header.clone().unwrap_or_else(|| String::from("-"))];
+        if let Some(key) = &table.topology_key {
+            fields.push(key.rsplit('/').next().unwrap_or(key).to_string());
+        }
+        let max_skew = table.max_skew();
+        let counts = table
+            .topologies
+            .into_iter()
+            .map(|topology| format!("{}={}", topology.key, topology.count));
+        fields.extend(counts);
+        fields.push(format!("skew={max_skew}"));
+        fields.join(" ")
+    };
+
+    topology_tables
+        .into_iter()
+        .map(line)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Tables without a workload, such as pod and node, have no name and are skipped
+fn name_lines(topology_tables: Vec<TopologyTable>) -> String {
+    let line = |table: &TopologyTable| {
+        let name = resource_name(table.header.as_deref()?)?;
+        Some(format!("{name} skew={}", table.max_skew()))
+    };
+
+    topology_tables
+        .iter()
+        .filter_map(line)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// `[prefix/]apps/v1/deployment/web` to `deployment.apps/web`, prefixes such as namespace are dropped.
+// Combined tables of --aggregate-by-label are not resources and have no name
+fn resource_name(header: &str) -> Option<String> {
+    let mut segments = header.rsplit('/');
+    let name = segments.next()?;
+    let kind = segments.next().filter(|kind| *kind != AGGREGATE_HEADER)?;
+    let _version = segments.next()?;
+    let name = match segments.next() {
+        Some(group) => format!("{kind}.{group}/{name}"),
+        None => format!("{kind}/{name}"),
+    };
+    Some(name)
+}
+
+fn summary_text(topology_tables: &[TopologyTable], opts: &OutputOptions) -> String {
+    let skewed = topology_tables
+        .iter()
+        .filter(|table| table.max_skew() > 0)
+        .count();
+    let worst = topology_tables
+        .iter()
+        .filter(|table| table.max_skew() > 0)
+        .max_by_key(|table| table.max_skew())
+        .map(|table| {
+            let name = table.header.as_deref().unwrap_or_default();
+            format!("{name} (skew {})", table.max_skew())
+        })
+        .unwrap_or_else(|| String::from("-"));
+
+    let mut builder = Builder::default();
+    builder.push_record(["WORKLOADS", &topology_tables.len().to_string()]);
+    builder.push_record(["BALANCED", &(topology_tables.len() - skewed).to_string()]);
+    builder.push_record(["SKEWED", &skewed.to_string()]);
+    builder.push_record(["WORST", &worst]);
+
+    // Workloads per severity, by the worst status of their domains
+    if topology_tables.iter().any(|table| table.status().is_some()) {
+        for status in [Status::Ok, Status::Warn, Status::Crit] {
+            let count = topology_tables
+                .iter()
+                .filter(|table| table.status() == Some(status))
+                .count();
+            builder.push_record([status.to_string(), count.to_string()]);
+        }
+    }
+
+    let mut table = builder.build();
+    with_style(&mut table, opts, Some(String::from("SUMMARY")));
+
+    table.to_string()
+}
+
+pub fn out_compare(
+    tables: Vec<CompareTable>,
+    format: OutputFormat,
+    opts: &OutputOptions,
+) -> Result<String> {
+    let buf = match format {
+        OutputFormat::Text | OutputFormat::Summary | OutputFormat::Name => {
+            compare_text(tables, opts)
+        }
+        OutputFormat::Json => serde_json::to_string_pretty(&tables)?,
+        OutputFormat::Yaml => serde_yaml::to_string(&tables)?,
+    };
+    Ok(buf)
+}
+
+// Domains as rows and the two contexts as column pairs, differences are marked with `*`
+fn compare_text(compare_tables: Vec<CompareTable>, opts: &OutputOptions) -> String {
+    let counts = |counts: Option<Counts>| match counts {
+        Some(counts) => [counts.count.to_string(), counts.skew.to_string()],
+        None => [String::from("-"), String::from("-")],
+    };
+
+    let view_table = |compare_table: CompareTable| {
+        let (context_a, context_b) = &compare_table.contexts;
+
+        let mut builder = Builder::default();
+        builder.push_record([
+            String::from("TOPOLOGY"),
+            format!("{context_a} COUNT"),
+            format!("{context_a} SKEW"),
+            format!("{context_b} COUNT"),
+            format!("{context_b} SKEW"),
+            String::from("DIFF"),
+        ]);
+        for row in compare_table.rows {
+            let [count_a, skew_a] = counts(row.a);
+            let [count_b, skew_b] = counts(row.b);
+            let diff = if row.diff { "*" } else { "" };
+            builder.push_record([row.key, count_a, skew_a, count_b, skew_b, diff.to_string()]);
+        }
+
+        let mut table = builder.build();
+        with_style(&mut table, opts, compare_table.header);
+
+        table.to_string()
+    };
+
+    compare_tables
+        .into_iter()
+        .map(view_table)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn profile_text(timings: Vec<Timing>, opts: &OutputOptions) -> String {
+    let mut table = Table::new(timings);
+    with_style(&mut table, opts, None);
+    table.to_string()
+}
+
+pub fn out_plan(
+    calls: Vec<PlannedCall>,
+    format: OutputFormat,
+    opts: &OutputOptions,
+) -> Result<String> {
+    let buf = match format {
+        OutputFormat::Text | OutputFormat::Summary | OutputFormat::Name => {
+            let mut table = Table::new(calls);
+            with_style(&mut table, opts, None);
+            table.to_string()
+        }
+        OutputFormat::Json => serde_json::to_string_pretty(&calls)?,
+        OutputFormat::Yaml => serde_yaml::to_string(&calls)?,
+    };
+    Ok(buf)
+}
+
+pub fn out_health(health: Health, format: OutputFormat, opts: &OutputOptions) -> Result<String> {
+    let buf = match format {
+        OutputFormat::Name => bail!("Output format name is not supported by the health summary"),
+        OutputFormat::Text | OutputFormat::Summary => {
+            let mut builder = Builder::default();
+            builder.push_record(["WORKLOADS", &health.workloads.to_string()]);
+            builder.push_record(["BALANCED", &health.balanced.to_string()]);
+            builder.push_record(["SKEWED", &health.skewed.to_string()]);
+
+            let mut tables = [
+                (builder.build(), "HEALTH"),
+                (Table::new(health.namespaces), "NAMESPACES"),
+                (Table::new(health.worst), "WORST"),
+                (Table::new(health.domains), "DOMAINS"),
+            ];
+            tables
+                .iter_mut()
+                .map(|(table, title)| {
+                    with_style(table, opts, Some(title.to_string()));
+                    table.to_string()
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        OutputFormat::Json => serde_json::to_string_pretty(&health)?,
+        OutputFormat::Yaml => serde_yaml::to_string(&health)?,
+    };
+    Ok(buf)
+}
+
+pub fn out_history(
+    trends: Vec<Trend>,
+    format: OutputFormat,
+    opts: &OutputOptions,
+) -> Result<String> {
+    let buf = match format {
+        OutputFormat::Text | OutputFormat::Summary | OutputFormat::Name => {
+            let mut table = Table::new(trends);
+            with_style(&mut table, opts, None);
+            table.to_string()
+        }
+        OutputFormat::Json => serde_json::to_string_pretty(&trends)?,
+        OutputFormat::Yaml => serde_yaml::to_string(&trends)?,
+    };
+    Ok(buf)
+}
+
+// Optional columns are shown only when at least one row has a value
+fn hide_empty_columns(table: &mut Table) {
+    if table.count_rows() <= 1 {
+        return;
+    }
+
+    let records = table.get_records();
+    let is_empty =
+        |col: &usize| (1..table.count_rows()).all(|row| records.get_text((row, *col)).is_empty());
+
+    let empty_columns = (0..table.count_columns())
+        .filter(is_empty)
+        .collect::<Vec<_>>();
+
+    // Remove from the right so that the remaining indexes are not shifted
+    for col in empty_columns.into_iter().rev() {
+        table.with(Disable::column(Columns::single(col)));
+    }
+}
+
+fn json(topologies: Vec<TopologyTable>) -> Result<String> {
     Ok(serde_json::to_string_pretty(&topologies)?)
 }
 
-fn yaml(topologies: TopologyTables) -> Result<String> {
+fn yaml(topologies: Vec<TopologyTable>) -> Result<String> {
     Ok(serde_yaml::to_string(&topologies)?)
 }
+
+pub fn write_file(text: &str, path: &Path, append: bool) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .with_context(|| format!("Fail to open output file: {}", path.display()))?;
+
+    writeln!(file, "{text}")
+        .with_context(|| format!("Fail to write output file: {}", path.display()))?;
+
+    Ok(())
+}
+
+// Exit status of sh when the command is not found
+const COMMAND_NOT_FOUND: i32 = 127;
+
+// Pipe the output through $PAGER like git does.
+// Short output is printed as is because less quits if it fits on one screen.
+pub fn page(text: &str) -> Result<()> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| DEFAULT_PAGER.to_string());
+    page_to(text, &pager, &mut io::stdout())
+}
+
+// Without a working pager the text is written to `out`, so that nothing is lost
+fn page_to(text: &str, pager: &str, out: &mut impl Write) -> Result<()> {
+    if pager.is_empty() || pager == "cat" {
+        writeln!(out, "{text}")?;
+        return Ok(());
+    }
+
+    let less = env::var("LESS").unwrap_or_else(|_| DEFAULT_LESS.to_string());
+    let spawned = Command::new("sh")
+        .arg("-c")
+        .arg(pager)
+        .env("LESS", less)
+        .stdin(Stdio::piped())
+        .spawn()
+        .ok();
+
+    let Some(mut child) = spawned else {
+        writeln!(out, "{text}")?;
+        return Ok(());
+    };
+
+    // The pager may exit before reading everything (e.g. `q` in less)
+    let written = match child.stdin.take() {
+        Some(mut stdin) => writeln!(stdin, "{text}").is_ok(),
+        None => false,
+    };
+    let status = child
+        .wait()
+        .with_context(|| format!("Fail to wait pager: {pager}"))?;
+
+    // A missing pager, or one that failed before reading the text, shows nothing
+    let not_found = status.code() == Some(COMMAND_NOT_FOUND);
+    if not_found || (!written && !status.success()) {
+        log::warn!("Fail to run pager: {pager}, {status}");
+        writeln!(out, "{text}")?;
+    }
+
+    Ok(())
+}
+
+// Numbered list narrowed down by typing, like fzf but line based.
+// Returns the index of the picked name in `names`.
+pub fn pick(names: &[String], mut input: impl BufRead, mut output: impl Write) -> Result<usize> {
+    let mut query = String::new();
+    loop {
+        let candidates = names
+            .iter()
+            .enumerate()
+            .filter(|(_, name)| is_fuzzy_match(name, &query))
+            .collect::<Vec<_>>();
+
+        if candidates.is_empty() {
+            writeln!(output, "No match: {query}")?;
+            query.clear();
+            continue;
+        }
+        for (number, (_, name)) in candidates.iter().enumerate() {
+            writeln!(output, "{:>3}) {name}", number + 1)?;
+        }
+        write!(output, "Select a number or type to filter: ")?;
+        output.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            bail!("No workload selected");
+        }
+        let line = line.trim();
+
+        match line.parse::<usize>() {
+            Result::Ok(number) if (1..=candidates.len()).contains(&number) => {
+                return Ok(candidates[number - 1].0);
+            }
+            _ => query = line.to_string(),
+        }
+    }
+}
+
+// Characters of the query appear in order, case-insensitively
+fn is_fuzzy_match(name: &str, query: &str) -> bool {
+    let mut chars = name.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|q| chars.any(|c| c == q))
+}
+
+// Progress of long scans on stderr, shown only when stderr is a terminal
+// so that redirected output and logs are not polluted
+#[derive(Debug)]
+pub struct Progress {
+    total: usize,
+    done: AtomicUsize,
+    enabled: bool,
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Self {
+        Self {
+            total,
+            done: AtomicUsize::new(0),
+            enabled: io::stderr().is_terminal(),
+        }
+    }
+
+    pub fn inc(&self, message: &str) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.enabled {
+            eprint!("\r\x1b[K[{done}/{}] {message}", self.total);
+        }
+    }
+
+    pub fn finish(&self) {
+        if self.enabled {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Thresholds, Topologies};
+    use std::collections::{BTreeSet, HashSet};
+
+    fn topology_table(values: &[&str], header: &str, allowed: Option<u32>) -> TopologyTable {
+        let domains = HashSet::from([String::from("zone-a"), String::from("zone-b")]);
+        let topology_values = values.iter().map(ToString::to_string).collect();
+
+        TopologyTable::create(topology_values, &domains, Some(header.to_string()))
+            .with_allowed(allowed)
+    }
+
+    #[test]
+    fn page_without_pager() -> Result<()> {
+        // Same as PAGER=/nonexistent, the text is printed instead of being lost
+        let mut out = Vec::new();
+        page_to("web", "/nonexistent", &mut out)?;
+        assert_eq!(String::from_utf8(out)?, "web\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn into_ordered_by_skew() {
+        let tables = || {
+            TopologyTables::from(BTreeSet::from([
+                topology_table(&["zone-a", "zone-b"], "a", None),
+                topology_table(&["zone-a", "zone-a", "zone-a"], "b", None),
+                topology_table(&["zone-a"], "c", None),
+            ]))
+        };
+        let headers = |order| {
+            tables()
+                .into_ordered(&order)
+                .into_iter()
+                .filter_map(|table| table.header)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(headers(TableOrder::Name), vec!["a", "b", "c"]);
+        assert_eq!(headers(TableOrder::Skew), vec!["b", "c", "a"]);
+    }
+
+    #[test]
+    fn text_grouped_with_worst() {
+        let table = |values: &[&str], header: &str, group: &str| TopologyTable {
+            group: Some(group.to_string()),
+            ..topology_table(values, header, None)
+        };
+        let tables = TopologyTables::from(BTreeSet::from([
+            table(&["zone-a", "zone-b"], "payments/a", "payments"),
+            table(&["zone-a", "zone-a", "zone-a"], "search/b", "search"),
+            table(&["zone-a", "zone-a"], "payments/c", "payments"),
+        ]));
+
+        // Tables of a group are kept together when ordered by skew
+        let tables = tables.into_ordered(&TableOrder::Skew);
+        let headers = tables
+            .iter()
+            .filter_map(|table| table.header.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(headers, vec!["payments/c", "payments/a", "search/b"]);
+
+        let out = text(tables, &OutputOptions::default());
+        let payments = out.find("payments WORST").unwrap();
+        let search = out.find("search WORST").unwrap();
+        assert!(payments < out.find("payments/a").unwrap());
+        assert!(out.find("payments/a").unwrap() < search);
+    }
+
+    #[test]
+    fn text_hide_empty_columns() {
+        let out = text(
+            vec![topology_table(&["zone-a"], "a", None)],
+            &OutputOptions::default(),
+        );
+        assert!(out.contains("SKEW"));
+        assert!(!out.contains("ALLOWED"));
+
+        let out = text(
+            vec![topology_table(&["zone-a"], "a", Some(1))],
+            &OutputOptions::default(),
+        );
+        assert!(out.contains("ALLOWED"));
+
+        let empty = TopologyTable::new(Topologies::default(), None);
+        let out = text(vec![empty], &OutputOptions::default());
+        assert!(out.contains("TOPOLOGY"));
+    }
+
+    #[test]
+    fn text_header_with_scope() {
+        let tables =
+            TopologyTables::from(BTreeSet::from([topology_table(&["zone-a"], "web", None)]))
+                .with_scope("topology.kubernetes.io/zone", Some("prod"));
+        let out = text(
+            tables.into_ordered(&TableOrder::Name),
+            &OutputOptions::default(),
+        );
+        assert!(out.contains("web — topology.kubernetes.io/zone — ns: prod"));
+
+        // The constraint is shown once in the title, not on every domain row
+        let table = topology_table(&["zone-a"], "web", None)
+            .with_constraint(String::from("kubernetes.io/hostname"));
+        let out = text(vec![table], &OutputOptions::default());
+        assert!(out.contains("web — constraint: kubernetes.io/hostname"));
+        assert!(!out.contains("CONSTRAINT"));
+    }
+
+    #[test]
+    fn out_with_totals() -> Result<()> {
+        let tables = TopologyTables::from(BTreeSet::from([
+            topology_table(&["zone-a", "zone-b"], "a", None),
+            topology_table(&["zone-a", "zone-a", "zone-a"], "b", None),
+        ]));
+        let out = out(
+            tables,
+            OutputFormat::Text,
+            TableOrder::Name,
+            &OutputOptions {
+                totals: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert!(out.contains("TOTAL"));
+        assert!(out.contains("SUMMARY"));
+        assert!(out.contains("b (skew 3)"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn out_with_totals_and_status() -> Result<()> {
+        let tables = TopologyTables::from(BTreeSet::from([
+            topology_table(&["zone-a", "zone-b"], "a", None),
+            topology_table(&["zone-a", "zone-a", "zone-a"], "b", None),
+        ]))
+        .with_status(&Thresholds {
+            warn: Some(1),
+            crit: Some(3),
+        });
+        let out = out(
+            tables,
+            OutputFormat::Text,
+            TableOrder::Name,
+            &OutputOptions {
+                totals: true,
+                ..Default::default()
+            },
+        )?;
+
+        let row = |cells: [&str; 2]| out.lines().any(|line| line.split_whitespace().eq(cells));
+        assert!(row(["OK", "1"]));
+        assert!(row(["WARN", "0"]));
+        assert!(row(["CRIT", "1"]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn out_summary() -> Result<()> {
+        let tables = TopologyTables::from(BTreeSet::from([topology_table(
+            &["zone-a", "zone-a", "zone-b"],
+            "web",
+            None,
+        )]))
+        .with_scope("topology.kubernetes.io/zone", Some("default"));
+        let out = out(
+            tables,
+            OutputFormat::Summary,
+            TableOrder::Name,
+            &OutputOptions::default(),
+        )?;
+
+        assert_eq!(out, "web zone zone-a=2 zone-b=1 skew=1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn text_ascii_style() {
+        let tables =
+            TopologyTables::from(BTreeSet::from([topology_table(&["zone-a"], "web", None)]))
+                .with_scope("topology.kubernetes.io/zone", Some("prod"));
+        let out = text(
+            tables.into_ordered(&TableOrder::Name),
+            &OutputOptions {
+                totals: true,
+                style: TableStyle::Ascii,
+                ..Default::default()
+            },
+        );
+
+        assert!(out.is_ascii());
+        assert!(out.contains("web - topology.kubernetes.io/zone - ns: prod"));
+    }
+
+    #[test]
+    fn text_max_width() {
+        let header = "apps/v1/deployment/a-very-long-deployment-name-to-be-truncated";
+        let tables =
+            TopologyTables::from(BTreeSet::from([topology_table(&["zone-a"], header, None)]));
+        let opts = OutputOptions {
+            max_width: Some(30),
+            ..Default::default()
+        };
+        let out = text(tables.into_ordered(&TableOrder::Name), &opts);
+
+        assert!(out.lines().all(|line| line.chars().count() <= 30));
+        assert!(out.contains("…"));
+    }
+
+    #[test]
+    fn out_group_by_kind() -> Result<()> {
+        let tables = TopologyTables::from(BTreeSet::from([
+            topology_table(&["zone-a"], "apps/v1/deployment/web", None),
+            topology_table(&["zone-a"], "apps/v1/statefulset/db", None),
+            topology_table(&["zone-a"], "default/apps/v1/deployment/api", None),
+        ]));
+        let opts = OutputOptions {
+            group_output: Some(GroupOutput::Kind),
+            ..Default::default()
+        };
+        let out = out(tables, OutputFormat::Json, TableOrder::Name, &opts)?;
+        let value = serde_json::from_str::<serde_json::Value>(&out)?;
+
+        assert_eq!(value["deployment"].as_array().map(Vec::len), Some(2));
+        assert_eq!(value["statefulset"].as_array().map(Vec::len), Some(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn out_name() -> Result<()> {
+        let tables = TopologyTables::from(BTreeSet::from([
+            topology_table(&["zone-a"], "apps/v1/deployment/web", None),
+            topology_table(&["zone-a", "zone-b"], "prod/batch/v1/job/backup", None),
+            topology_table(&["zone-a"], "prod/aggregate/checkout", None),
+        ]));
+        let out = out(
+            tables,
+            OutputFormat::Name,
+            TableOrder::Name,
+            &Default::default(),
+        )?;
+
+        assert_eq!(out, "deployment.apps/web skew=1\njob.batch/backup skew=0");
+
+        Ok(())
+    }
+
+    #[test]
+    fn pick_by_number_after_filter() -> Result<()> {
+        let names = ["api", "web", "web-canary"].map(String::from);
+        let mut output = Vec::new();
+        let index = pick(&names, "wc\n1\n".as_bytes(), &mut output)?;
+
+        assert_eq!(index, 2);
+        let output = String::from_utf8(output)?;
+        assert!(output.contains("  1) web-canary"));
+
+        let index = pick(&names, "x\n2\n".as_bytes(), Vec::new())?;
+        assert_eq!(index, 1);
+
+        assert!(pick(&names, "".as_bytes(), Vec::new()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_file_append() -> Result<()> {
+        let path = std::env::temp_dir().join("kubectl-topology-skew-write-file-append.txt");
+
+        write_file("first", &path, false)?;
+        write_file("second", &path, true)?;
+        assert_eq!(std::fs::read_to_string(&path)?, "first\nsecond\n");
+
+        write_file("third", &path, false)?;
+        assert_eq!(std::fs::read_to_string(&path)?, "third\n");
+
+        std::fs::remove_file(&path)?;
+
+        Ok(())
+    }
+}